@@ -147,5 +147,62 @@ fn efs_test() -> std::io::Result<()> {
     random_str_test(1000 * BLOCK_SZ);
     random_str_test(2000 * BLOCK_SZ);
 
+    // directories
+    let root_inode = Arc::new(root_inode);
+    let dira = root_inode.create_dir("dira").unwrap();
+    assert!(dira.is_dir());
+    assert!(root_inode.create_dir("dira").is_none());
+    let dirb = dira.create_dir("dirb").unwrap();
+    dirb.create("filec").unwrap();
+    assert_eq!(dirb.ls(), [".", "..", "filec"]);
+    let filec = root_inode.find_path("/dira/dirb/filec").unwrap();
+    filec.write_at(0, greet_str.as_bytes());
+    let filec = root_inode.find_path("dira/./dirb/../dirb/filec").unwrap();
+    assert_eq!(filec.size(), greet_str.len() as u32);
+    let len = filec.read_at(0, &mut buffer);
+    assert_eq!(greet_str, core::str::from_utf8(&buffer[..len]).unwrap());
+    assert_eq!(
+        root_inode.find_path("dira/..").unwrap().inode_id(),
+        root_inode.inode_id()
+    );
+    assert!(root_inode.find_path("dira/dirb/filec/..").is_none());
+    // only an empty directory is removed
+    assert!(dira.remove_dir("dirb").is_none());
+    assert!(dirb.remove_dir("filec").is_none());
+    assert!(dira.remove_dir("..").is_none());
+    // one entry more than a block holds, the last is moved into the hole
+    dirb.create_dir("dird").unwrap();
+    assert_eq!(dirb.nlink(), 3);
+    for i in 0..13 {
+        dirb.create(format!("file{}", i).as_str()).unwrap();
+    }
+    let dird = dirb.remove_dir("dird").unwrap();
+    assert_eq!(dird.nlink(), 0);
+    dird.dealloc();
+    assert_eq!(dirb.nlink(), 2);
+    assert!(dirb.find("dird").is_none());
+    assert_eq!(dirb.ls().len(), BLOCK_SZ / 32);
+    assert_eq!(dirb.ls()[3], "file12");
+    assert!(dira.find_path("dirb/filec").is_some());
+    assert!(dira.remove_dir("dirb").is_none());
+
+    // hard links
+    assert_eq!(filec.nlink(), 1);
+    assert!(root_inode.link("linkc", &filec));
+    assert!(!root_inode.link("linkc", &filec));
+    assert!(!root_inode.link("linkb", &dirb));
+    assert_eq!(filec.nlink(), 2);
+    assert_eq!(dirb.unlink("filec").unwrap().nlink(), 1);
+    assert!(dirb.find("filec").is_none());
+    let len = root_inode.find("linkc").unwrap().read_at(0, &mut buffer);
+    assert_eq!(greet_str, core::str::from_utf8(&buffer[..len]).unwrap());
+    assert!(dira.unlink("dirb").is_none());
+    // the inode of the last link is reclaimed
+    let filec_id = filec.inode_id();
+    let filec = root_inode.unlink("linkc").unwrap();
+    assert_eq!(filec.nlink(), 0);
+    filec.dealloc();
+    assert_eq!(root_inode.create("filed").unwrap().inode_id(), filec_id);
+
     Ok(())
 }
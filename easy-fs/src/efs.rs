@@ -88,7 +88,10 @@ impl EasyFileSystem {
                 disk_inode.initialize(DiskInodeType::Directory);
             });
         block_cache_sync_all();
-        Arc::new(Mutex::new(efs))
+        let efs = Arc::new(Mutex::new(efs));
+        // "." and ".." of the root directory
+        Self::root_inode(&efs).init_root();
+        efs
     }
     /// Open an existing EasyFileSystem
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
@@ -130,6 +133,13 @@ impl EasyFileSystem {
             (inode_id % inodes_per_block) as usize * inode_size,
         )
     }
+    /// Get inode id according to the inode block position, the inverse of `get_disk_inode_pos`
+    pub fn get_inode_id(&self, block_id: u32, block_offset: usize) -> u32 {
+        let inode_size = core::mem::size_of::<DiskInode>();
+        let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
+        (block_id - self.inode_area_start_block) * inodes_per_block
+            + (block_offset / inode_size) as u32
+    }
     /// Get data block position according to the data block id
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
@@ -138,6 +148,11 @@ impl EasyFileSystem {
     pub fn alloc_inode(&mut self) -> u32 {
         self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
     }
+    /// deallocate an inode according to its inode_id
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize)
+    }
 
     /// allocate a new data block, return its block position (block_id)
     pub fn alloc_data(&mut self) -> u32 {
@@ -14,8 +14,9 @@ use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter, Result};
 
 const EFS_MAGIC: u32 = 0x3b800001;
-const INODE_DIRECT_COUNT: usize = 28;
-const NAME_LENGTH_LIMIT: usize = 27;
+const INODE_DIRECT_COUNT: usize = 27;
+/// The longest name of a directory entry
+pub const NAME_LENGTH_LIMIT: usize = 27;
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
 const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
@@ -84,6 +85,10 @@ pub enum DiskInodeType {
     File,
     /// Directory type
     Directory,
+    /// Named pipe type, it has no data blocks
+    Fifo,
+    /// Unix-domain socket type, it has no data blocks
+    Socket,
 }
 
 type IndirectBlock = [u32; BLOCK_SZ / 4];
@@ -94,6 +99,8 @@ type DataBlock = [u8; BLOCK_SZ];
 pub struct DiskInode {
     /// file size
     pub size: u32,
+    /// number of directory entries linking to the inode
+    pub nlink: u32,
     /// array of direct block id
     pub direct: [u32; INODE_DIRECT_COUNT],
     /// one-level indirect block id
@@ -106,8 +113,16 @@ pub struct DiskInode {
 
 impl DiskInode {
     /// indirect1 and indirect2 block are allocated only when they are needed.
+    ///
+    /// A new inode is linked by its entry in a directory, a new directory
+    /// also by its own ".".
     pub fn initialize(&mut self, type_: DiskInodeType) {
         self.size = 0;
+        self.nlink = if type_ == DiskInodeType::Directory {
+            2
+        } else {
+            1
+        };
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
@@ -122,6 +137,14 @@ impl DiskInode {
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    /// inode is named pipe?
+    pub fn is_fifo(&self) -> bool {
+        self.type_ == DiskInodeType::Fifo
+    }
+    /// inode is unix-domain socket?
+    pub fn is_socket(&self) -> bool {
+        self.type_ == DiskInodeType::Socket
+    }
     /// Return block number correspond to size.
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
@@ -5,10 +5,11 @@
 //! NOTICE: The difference between [`Inode`] and [`DiskInode`]  can be seen from their names: DiskInode in a relatively fixed location within the disk block, while Inode Is a data structure placed in memory that records file inode information.
 use super::{
     block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    EasyFileSystem, DIRENT_SZ, NAME_LENGTH_LIMIT,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use spin::{Mutex, MutexGuard};
 
@@ -70,10 +71,13 @@ impl Inode {
         }
         None
     }
-    /// find the disk inode of the file with 'name'
+    /// find the disk inode of the file with 'name', None if this is not a directory
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
         let fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return None;
+            }
             self.find_inode_id(name, disk_inode).map(|inode_id| {
                 let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
                 Arc::new(Self::new(
@@ -85,6 +89,27 @@ impl Inode {
             })
         })
     }
+    /// get the inode of 'inode_id'
+    fn get_inode(&self, inode_id: u32, fs: &MutexGuard<EasyFileSystem>) -> Arc<Inode> {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        Arc::new(Self::new(
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        ))
+    }
+    /// find the disk inode of the file at 'path' from this directory
+    ///
+    /// The names in 'path' are separated by '/', "." and ".." are found
+    /// through their directory entries like any other name.
+    pub fn find_path(self: &Arc<Self>, path: &str) -> Option<Arc<Inode>> {
+        let mut inode = Arc::clone(self);
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            inode = inode.find(name)?;
+        }
+        Some(inode)
+    }
     /// increase the size of file( also known as 'disk inode')
     fn increase_size(
         &self,
@@ -102,8 +127,149 @@ impl Inode {
         }
         disk_inode.increase_size(new_size, v, &self.block_device);
     }
-    /// create a file with 'name' in the root directory
+    /// decrease the size of file( also known as 'disk inode'), dealloc blocks no longer needed
+    fn decrease_size(
+        &self,
+        new_size: u32,
+        disk_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) {
+        if new_size >= disk_inode.size {
+            return;
+        }
+        if DiskInode::total_blocks(new_size) == DiskInode::total_blocks(disk_inode.size) {
+            disk_inode.size = new_size;
+            return;
+        }
+        // rebuild the file with the data kept
+        let mut data = vec![0u8; new_size as usize];
+        disk_inode.read_at(0, &mut data, &self.block_device);
+        for data_block in disk_inode.clear_size(&self.block_device) {
+            fs.dealloc_data(data_block);
+        }
+        self.increase_size(new_size, disk_inode, fs);
+        disk_inode.write_at(0, &data, &self.block_device);
+    }
+    /// append a directory entry of 'name' to the directory
+    fn append_dirent(
+        &self,
+        name: &str,
+        inode_id: u32,
+        dir_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) {
+        let file_count = (dir_inode.size as usize) / DIRENT_SZ;
+        let new_size = (file_count + 1) * DIRENT_SZ;
+        // increase size
+        self.increase_size(new_size as u32, dir_inode, fs);
+        // write dirent
+        let dirent = DirEntry::new(name, inode_id);
+        dir_inode.write_at(
+            file_count * DIRENT_SZ,
+            dirent.as_bytes(),
+            &self.block_device,
+        );
+    }
+    /// remove the directory entry of 'name', the last entry is moved into its place
+    fn remove_dirent(
+        &self,
+        name: &str,
+        dir_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> bool {
+        let file_count = (dir_inode.size as usize) / DIRENT_SZ;
+        let mut dirent = DirEntry::empty();
+        let pos = (0..file_count).find(|i| {
+            dir_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+            dirent.name() == name
+        });
+        let pos = match pos {
+            Some(pos) => pos,
+            None => return false,
+        };
+        dir_inode.read_at(
+            (file_count - 1) * DIRENT_SZ,
+            dirent.as_bytes_mut(),
+            &self.block_device,
+        );
+        dir_inode.write_at(pos * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+        self.decrease_size(((file_count - 1) * DIRENT_SZ) as u32, dir_inode, fs);
+        true
+    }
+    /// write the "." and ".." entries of a new directory
+    fn init_dir(&self, parent_id: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        let inode_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+        self.modify_disk_inode(|dir_inode| {
+            self.append_dirent(".", inode_id, dir_inode, fs);
+            self.append_dirent("..", parent_id, dir_inode, fs);
+        });
+    }
+    /// write the "." and ".." entries of the root directory, both are the root itself
+    pub(crate) fn init_root(&self) {
+        let mut fs = self.fs.lock();
+        let root_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+        self.init_dir(root_id, &mut fs);
+        block_cache_sync_all();
+    }
+    /// get the inode id of the inode
+    pub fn inode_id(&self) -> u32 {
+        self.fs
+            .lock()
+            .get_inode_id(self.block_id as u32, self.block_offset)
+    }
+    /// the number of directory entries linking to the inode
+    pub fn nlink(&self) -> u32 {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.nlink)
+    }
+    /// the size of the file in bytes
+    pub fn size(&self) -> u32 {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size)
+    }
+    /// is the inode a directory?
+    pub fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+    /// is the inode a named pipe?
+    pub fn is_fifo(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_fifo())
+    }
+    /// is the inode a unix-domain socket?
+    pub fn is_socket(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_socket())
+    }
+    /// can 'name' be the name of a directory entry?
+    fn valid_name(name: &str) -> bool {
+        !name.is_empty() && name.len() <= NAME_LENGTH_LIMIT && !name.contains('/')
+    }
+    /// create a file with 'name' in the directory
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+    /// create a directory with 'name' in the directory
+    pub fn create_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory)
+    }
+    /// create a named pipe with 'name' in the directory
+    pub fn create_fifo(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Fifo)
+    }
+    /// create a unix-domain socket with 'name' in the directory
+    pub fn create_socket(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Socket)
+    }
+    /// create an inode of `type_` with 'name' in the directory
+    ///
+    /// Return None if 'name' exists, or it is empty, too long or has a '/'.
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> Option<Arc<Inode>> {
+        if !Self::valid_name(name) {
+            return None;
+        }
+        let is_dir = type_ == DiskInodeType::Directory;
         let mut fs = self.fs.lock();
         let op = |root_inode: &mut DiskInode| {
             // assert it is a directory
@@ -122,37 +288,122 @@ impl Inode {
         get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
             .lock()
             .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
+                new_inode.initialize(type_);
             });
         self.modify_disk_inode(|root_inode| {
             // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
+            self.append_dirent(name, new_inode_id, root_inode, &mut fs);
+            if is_dir {
+                // ".." of the new directory
+                root_inode.nlink += 1;
+            }
         });
 
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        let inode = self.get_inode(new_inode_id, &fs);
+        if is_dir {
+            // ".." of the new directory is this one
+            let parent_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+            inode.init_dir(parent_id, &mut fs);
+        }
         block_cache_sync_all();
         // return inode
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+        Some(inode)
         // release efs lock automatically by compiler
     }
-    /// create a directory with 'name' in the root directory
+    /// link 'inode' into the directory with 'name'
+    ///
+    /// Return false if 'name' exists, or it is empty, too long or has a '/',
+    /// or 'inode' is a directory.
+    pub fn link(&self, name: &str, inode: &Inode) -> bool {
+        if !Self::valid_name(name) {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        if inode.read_disk_inode(|disk_inode| disk_inode.is_dir()) {
+            return false;
+        }
+        if self
+            .read_disk_inode(|dir_inode| self.find_inode_id(name, dir_inode))
+            .is_some()
+        {
+            return false;
+        }
+        let inode_id = fs.get_inode_id(inode.block_id as u32, inode.block_offset);
+        self.modify_disk_inode(|dir_inode| {
+            self.append_dirent(name, inode_id, dir_inode, &mut fs);
+        });
+        inode.modify_disk_inode(|disk_inode| disk_inode.nlink += 1);
+        block_cache_sync_all();
+        true
+    }
+    /// remove the entry of the file with 'name' from the directory
     ///
-    /// list the file names in the root directory
+    /// Return the file, None if there is no entry of 'name' or it is a
+    /// directory. A file with no links left is to be dealloced with
+    /// [`Inode::dealloc`] once it is no longer used.
+    pub fn unlink(&self, name: &str) -> Option<Arc<Inode>> {
+        self.remove_entry(name, false)
+    }
+    /// remove the empty directory with 'name' from the directory
+    ///
+    /// Return the directory, None if there is no directory of 'name', it is
+    /// not empty, or 'name' is "." or "..". The directory has no links left,
+    /// it is to be dealloced with [`Inode::dealloc`] once it is no longer used.
+    pub fn remove_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.remove_entry(name, true)
+    }
+    /// remove the entry of 'name', of a directory if `is_dir`, and unlink its inode
+    fn remove_entry(&self, name: &str, is_dir: bool) -> Option<Arc<Inode>> {
+        if name == "." || name == ".." {
+            return None;
+        }
+        let mut fs = self.fs.lock();
+        let inode_id = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return None;
+            }
+            self.find_inode_id(name, disk_inode)
+        })?;
+        let inode = self.get_inode(inode_id, &fs);
+        let removable = inode.read_disk_inode(|disk_inode| {
+            if is_dir {
+                // an empty directory has only "." and ".."
+                disk_inode.is_dir() && disk_inode.size as usize == 2 * DIRENT_SZ
+            } else {
+                !disk_inode.is_dir()
+            }
+        });
+        if !removable {
+            return None;
+        }
+        self.modify_disk_inode(|dir_inode| {
+            self.remove_dirent(name, dir_inode, &mut fs);
+            if is_dir {
+                // ".." of the directory is gone
+                dir_inode.nlink -= 1;
+            }
+        });
+        inode.modify_disk_inode(|disk_inode| {
+            // "." of a directory is gone with its entry
+            disk_inode.nlink -= if is_dir { 2 } else { 1 };
+        });
+        block_cache_sync_all();
+        Some(inode)
+    }
+    /// dealloc the data blocks and the disk inode of an inode with no links left
+    pub fn dealloc(&self) {
+        let mut fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            assert_eq!(disk_inode.nlink, 0);
+            for data_block in disk_inode.clear_size(&self.block_device) {
+                fs.dealloc_data(data_block);
+            }
+        });
+        let inode_id = fs.get_inode_id(self.block_id as u32, self.block_offset);
+        fs.dealloc_inode(inode_id);
+        block_cache_sync_all();
+    }
+    /// list the file names in the directory
     pub fn ls(&self) -> Vec<String> {
         let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
@@ -0,0 +1,140 @@
+use super::{File, FsError};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::{block_current_interruptible, current_task, wakeup_task, TaskControlBlock};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// The largest value of the counter
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+/// eventfd, a counter read and written in 8 bytes
+///
+/// A write adds to the counter and a read takes it, waiting while it is
+/// zero. A read or write with less than 8 bytes, or a write of `u64::MAX`,
+/// fails with `FsError::Invalid`.
+pub struct EventFd {
+    inner: UPSafeCell<EventFdInner>,
+}
+
+struct EventFdInner {
+    count: u64,
+    /// a read takes 1 from the counter instead of all of it
+    semaphore: bool,
+    /// is O_NONBLOCK set?
+    nonblocking: bool,
+    /// tasks waiting for the counter to change, or polling it
+    waiters: Vec<Arc<TaskControlBlock>>,
+}
+
+impl EventFdInner {
+    fn wake_waiters(&mut self) {
+        for task in self.waiters.drain(..) {
+            wakeup_task(task);
+        }
+    }
+}
+
+impl EventFd {
+    /// Create an eventfd with the counter at `count`
+    pub fn new(count: u64, semaphore: bool) -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(EventFdInner {
+                    count,
+                    semaphore,
+                    nonblocking: false,
+                    waiters: Vec::new(),
+                })
+            },
+        }
+    }
+}
+
+impl File for EventFd {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> Result<usize, FsError> {
+        trace!("kernel: EventFd::read");
+        if buf.len() < 8 {
+            return Err(FsError::Invalid);
+        }
+        let task = current_task().unwrap();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.count == 0 {
+                if inner.nonblocking {
+                    return Ok(0);
+                }
+                inner.waiters.push(Arc::clone(&task));
+                drop(inner);
+                if !block_current_interruptible() {
+                    self.unregister_waiter(&task);
+                    return Err(FsError::Interrupted);
+                }
+                continue;
+            }
+            let value = if inner.semaphore { 1 } else { inner.count };
+            inner.count -= value;
+            // there is room for the writers now
+            inner.wake_waiters();
+            drop(inner);
+            return Ok(buf.write_bytes(&value.to_ne_bytes()));
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> Result<usize, FsError> {
+        trace!("kernel: EventFd::write");
+        let mut bytes = [0u8; 8];
+        if buf.read_bytes(&mut bytes) < 8 {
+            return Err(FsError::Invalid);
+        }
+        let value = u64::from_ne_bytes(bytes);
+        if value == u64::MAX {
+            return Err(FsError::Invalid);
+        }
+        let task = current_task().unwrap();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if EVENTFD_MAX - inner.count >= value {
+                inner.count += value;
+                inner.wake_waiters();
+                return Ok(8);
+            }
+            if inner.nonblocking {
+                return Ok(0);
+            }
+            inner.waiters.push(Arc::clone(&task));
+            drop(inner);
+            if !block_current_interruptible() {
+                self.unregister_waiter(&task);
+                return Err(FsError::Interrupted);
+            }
+        }
+    }
+    fn read_ready(&self) -> bool {
+        self.inner.exclusive_access().count > 0
+    }
+    fn write_ready(&self) -> bool {
+        self.inner.exclusive_access().count < EVENTFD_MAX
+    }
+    fn register_waiter(&self, task: &Arc<TaskControlBlock>) -> bool {
+        self.inner.exclusive_access().waiters.push(Arc::clone(task));
+        true
+    }
+    fn unregister_waiter(&self, task: &Arc<TaskControlBlock>) {
+        self.inner
+            .exclusive_access()
+            .waiters
+            .retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+    fn nonblocking(&self) -> bool {
+        self.inner.exclusive_access().nonblocking
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.inner.exclusive_access().nonblocking = nonblocking;
+    }
+}
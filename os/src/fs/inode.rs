@@ -1,11 +1,13 @@
-use super::File;
+use super::{forget_fifo, open_fifo, unbind_socket, File, Stat, StatMode};
 use crate::drivers::BLOCK_DEVICE;
 use crate::mm::UserBuffer;
 use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::*;
-use easy_fs::{EasyFileSystem, Inode};
+use easy_fs::{EasyFileSystem, Inode, NAME_LENGTH_LIMIT};
 use lazy_static::*;
 
 /// inode in memory
@@ -18,16 +20,27 @@ pub struct OSInode {
 pub struct OSInodeInner {
     offset: usize,
     inode: Arc<Inode>,
+    nonblocking: bool,
 }
 
 impl OSInode {
     /// create a new inode in memory
     pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
         trace!("kernel: OSInode::new");
+        *OPEN_INODES
+            .exclusive_access()
+            .entry(inode.inode_id())
+            .or_insert(0) += 1;
         Self {
             readable,
             writable,
-            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, inode }) },
+            inner: unsafe {
+                UPSafeCell::new(OSInodeInner {
+                    offset: 0,
+                    inode,
+                    nonblocking: false,
+                })
+            },
         }
     }
     /// read all data from the inode in memory
@@ -48,18 +61,56 @@ impl OSInode {
     }
 }
 
+impl Drop for OSInode {
+    fn drop(&mut self) {
+        let inode = Arc::clone(&self.inner.exclusive_access().inode);
+        let inode_id = inode.inode_id();
+        let mut open_inodes = OPEN_INODES.exclusive_access();
+        let count = open_inodes.get_mut(&inode_id).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            open_inodes.remove(&inode_id);
+        }
+        drop(open_inodes);
+        release(&inode);
+    }
+}
+
 lazy_static! {
     pub static ref ROOT_INODE: Arc<Inode> = {
         let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
+    /// OPEN_INODES: the number of open files of each inode, by inode id
+    static ref OPEN_INODES: UPSafeCell<BTreeMap<u32, usize>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Dealloc `inode` if it has no links left and it is not open
+///
+/// The open ends of a FIFO and a bound socket are not counted, they keep
+/// working but cannot be reached through the inode id any more.
+fn release(inode: &Inode) {
+    let open = OPEN_INODES
+        .exclusive_access()
+        .contains_key(&inode.inode_id());
+    if inode.nlink() == 0 && !open {
+        if inode.is_fifo() {
+            forget_fifo(inode.inode_id());
+        } else if inode.is_socket() {
+            unbind_socket(inode.inode_id());
+        }
+        inode.dealloc();
+    }
 }
 
 /// List all apps in the root directory
 pub fn list_apps() {
     println!("/**** APPS ****");
     for app in ROOT_INODE.ls() {
-        println!("{}", app);
+        if app != "." && app != ".." {
+            println!("{}", app);
+        }
     }
     println!("**************/");
 }
@@ -77,6 +128,8 @@ bitflags! {
         const CREATE = 1 << 9;
         /// truncate file size to 0
         const TRUNC = 1 << 10;
+        /// read and write return EAGAIN instead of blocking
+        const NONBLOCK = 1 << 11;
     }
 }
 
@@ -94,28 +147,227 @@ impl OpenFlags {
     }
 }
 
+/// The errors of operations on paths
+#[derive(Debug)]
+pub enum FsError {
+    /// the file, or a directory of the path, does not exist
+    NotFound,
+    /// the directory of the path is not a directory
+    NotDir,
+    /// the file exists already
+    Exists,
+    /// the directory is not empty
+    NotEmpty,
+    /// the last name of the path is too long
+    NameTooLong,
+    /// the last name of the path can not be used, like "."
+    Invalid,
+    /// the directory is in use, like the root
+    Busy,
+    /// the file is a directory
+    IsDir,
+    /// the operation is not permitted on the file, like linking a directory
+    NotPermitted,
+    /// a signal interrupts the wait for the file
+    Interrupted,
+}
+
+/// Join `path` to the directory `cwd` unless it is absolute
+pub fn join_path(cwd: &str, path: &str) -> String {
+    if path.starts_with('/') {
+        return String::from(path);
+    }
+    let mut joined = String::from(cwd.trim_end_matches('/'));
+    joined.push('/');
+    joined.push_str(path);
+    joined
+}
+
+/// Normalize the absolute `path`, "." and empty names are dropped and ".."
+/// drops the name before it
+pub fn normalize_path(path: &str) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                names.pop();
+            }
+            name => names.push(name),
+        }
+    }
+    if names.is_empty() {
+        return String::from("/");
+    }
+    let mut normalized = String::new();
+    for name in names {
+        normalized.push('/');
+        normalized.push_str(name);
+    }
+    normalized
+}
+
+/// Find the inode at `path` from the root directory
+fn find_inode(path: &str) -> Option<Arc<Inode>> {
+    ROOT_INODE.find_path(path)
+}
+
+/// Find the directory `path` is in, return it with the last name of `path`
+fn find_parent(path: &str) -> Result<(Arc<Inode>, &str), FsError> {
+    let path = path.trim_end_matches('/');
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if name.is_empty() {
+        return Err(FsError::Invalid);
+    }
+    if name.len() > NAME_LENGTH_LIMIT {
+        return Err(FsError::NameTooLong);
+    }
+    let dir = find_inode(dir).ok_or(FsError::NotFound)?;
+    if !dir.is_dir() {
+        return Err(FsError::NotDir);
+    }
+    Ok((dir, name))
+}
+
 /// Open a file
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
-    trace!("kernel: open_file: name = {}, flags = {:?}", name, flags);
+///
+/// A directory can only be opened to read its entries.
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+    trace!("kernel: open_file: path = {}, flags = {:?}", path, flags);
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
-        } else {
+    let clear = flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC);
+    let inode = match find_inode(path) {
+        Some(inode) => {
+            if inode.is_dir() && (writable || clear) {
+                return None;
+            }
+            if clear {
+                // clear size
+                inode.clear();
+            }
+            inode
+        }
+        None if flags.contains(OpenFlags::CREATE) => {
             // create file
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
+            let (dir, name) = find_parent(path).ok()?;
+            dir.create(name)?
+        }
+        None => return None,
+    };
+    Some(Arc::new(OSInode::new(readable, writable, inode)))
+}
+
+/// Open a file for a file descriptor, a FIFO opens as an end of its pipe
+///
+/// A socket can not be opened, it is connected to instead.
+pub fn open(path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+    let nonblocking = flags.contains(OpenFlags::NONBLOCK);
+    if let Some(inode) = find_inode(path) {
+        if inode.is_socket() {
+            return None;
         }
+        if inode.is_fifo() {
+            let (readable, writable) = flags.read_write();
+            return Some(open_fifo(inode.inode_id(), readable, writable, nonblocking));
+        }
+    }
+    let file = open_file(path, flags)?;
+    file.set_nonblocking(nonblocking);
+    Some(file as Arc<dyn File + Send + Sync>)
+}
+
+/// Create a FIFO
+pub fn make_fifo(path: &str) -> Result<(), FsError> {
+    trace!("kernel: make_fifo: path = {}", path);
+    let (dir, name) = find_parent(path)?;
+    dir.create_fifo(name).map(|_| ()).ok_or(FsError::Exists)
+}
+
+/// Create a socket file, return its inode id
+pub fn make_socket(path: &str) -> Result<u32, FsError> {
+    trace!("kernel: make_socket: path = {}", path);
+    let (dir, name) = find_parent(path)?;
+    dir.create_socket(name)
+        .map(|inode| inode.inode_id())
+        .ok_or(FsError::Exists)
+}
+
+/// Find a socket file, return its inode id
+pub fn find_socket(path: &str) -> Option<u32> {
+    find_inode(path)
+        .filter(|inode| inode.is_socket())
+        .map(|inode| inode.inode_id())
+}
+
+/// Create a directory
+pub fn make_dir(path: &str) -> Result<(), FsError> {
+    trace!("kernel: make_dir: path = {}", path);
+    let (dir, name) = find_parent(path)?;
+    dir.create_dir(name).map(|_| ()).ok_or(FsError::Exists)
+}
+
+/// Remove an empty directory
+pub fn remove_dir(path: &str) -> Result<(), FsError> {
+    trace!("kernel: remove_dir: path = {}", path);
+    if path.trim_end_matches('/').is_empty() {
+        // the root directory
+        return Err(FsError::Busy);
+    }
+    let (dir, name) = find_parent(path)?;
+    if name == "." || name == ".." {
+        return Err(FsError::Invalid);
+    }
+    let inode = dir.find(name).ok_or(FsError::NotFound)?;
+    if !inode.is_dir() {
+        return Err(FsError::NotDir);
+    }
+    let inode = dir.remove_dir(name).ok_or(FsError::NotEmpty)?;
+    release(&inode);
+    Ok(())
+}
+
+/// Link the file at `old_path` to `new_path`
+pub fn link(old_path: &str, new_path: &str) -> Result<(), FsError> {
+    trace!(
+        "kernel: link: old_path = {}, new_path = {}",
+        old_path,
+        new_path
+    );
+    let inode = find_inode(old_path).ok_or(FsError::NotFound)?;
+    if inode.is_dir() {
+        return Err(FsError::NotPermitted);
+    }
+    let (dir, name) = find_parent(new_path)?;
+    if dir.link(name, &inode) {
+        Ok(())
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
-            }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+        Err(FsError::Exists)
+    }
+}
+
+/// Remove the link at `path` to a file
+///
+/// The file is dealloced with its last link, or with its last open file
+/// after that.
+pub fn unlink(path: &str) -> Result<(), FsError> {
+    trace!("kernel: unlink: path = {}", path);
+    let (dir, name) = find_parent(path)?;
+    let inode = dir.find(name).ok_or(FsError::NotFound)?;
+    if inode.is_dir() {
+        return Err(FsError::IsDir);
+    }
+    let inode = dir.unlink(name).ok_or(FsError::NotFound)?;
+    release(&inode);
+    Ok(())
+}
+
+/// Check that there is a directory at `path`
+pub fn check_dir(path: &str) -> Result<(), FsError> {
+    let inode = find_inode(path).ok_or(FsError::NotFound)?;
+    if inode.is_dir() {
+        Ok(())
+    } else {
+        Err(FsError::NotDir)
     }
 }
 
@@ -129,7 +381,7 @@ impl File for OSInode {
         self.writable
     }
     /// read file data into buffer
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    fn read(&self, mut buf: UserBuffer) -> Result<usize, FsError> {
         trace!("kernel: OSInode::read");
         let mut inner = self.inner.exclusive_access();
         let mut total_read_size = 0usize;
@@ -141,10 +393,10 @@ impl File for OSInode {
             inner.offset += read_size;
             total_read_size += read_size;
         }
-        total_read_size
+        Ok(total_read_size)
     }
     /// write buffer data into file
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn write(&self, buf: UserBuffer) -> Result<usize, FsError> {
         trace!("kernel: OSInode::write");
         let mut inner = self.inner.exclusive_access();
         let mut total_write_size = 0usize;
@@ -154,6 +406,28 @@ impl File for OSInode {
             inner.offset += write_size;
             total_write_size += write_size;
         }
-        total_write_size
+        Ok(total_write_size)
+    }
+    fn nonblocking(&self) -> bool {
+        self.inner.exclusive_access().nonblocking
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        self.inner.exclusive_access().nonblocking = nonblocking;
+    }
+    /// FIFOs and sockets are not opened as inodes, only files and directories
+    fn stat(&self) -> Stat {
+        let inner = self.inner.exclusive_access();
+        let mode = if inner.inode.is_dir() {
+            StatMode::DIR
+        } else {
+            StatMode::FILE
+        };
+        Stat::new(
+            0,
+            inner.inode.inode_id() as u64,
+            mode,
+            inner.inode.nlink(),
+            inner.inode.size() as u64,
+        )
     }
 }
@@ -1,10 +1,15 @@
-//! File trait & inode(dir, file, pipe, stdin, stdout)
+//! File trait & inode(dir, file, pipe, stdin, stdout, eventfd, signalfd, socket)
 
+mod eventfd;
 mod inode;
 mod pipe;
+mod signalfd;
+mod socket;
 mod stdio;
 
 use crate::mm::UserBuffer;
+use crate::task::TaskControlBlock;
+use alloc::sync::Arc;
 
 /// trait File for all file types
 pub trait File: Send + Sync {
@@ -13,9 +18,53 @@ pub trait File: Send + Sync {
     /// the file writable?
     fn writable(&self) -> bool;
     /// read from the file to buf, return the number of bytes read
-    fn read(&self, buf: UserBuffer) -> usize;
+    fn read(&self, buf: UserBuffer) -> Result<usize, FsError>;
     /// write to the file from buf, return the number of bytes written
-    fn write(&self, buf: UserBuffer) -> usize;
+    fn write(&self, buf: UserBuffer) -> Result<usize, FsError>;
+    /// would a read return at once, with data or at the end of file?
+    fn read_ready(&self) -> bool {
+        true
+    }
+    /// would a write return at once?
+    fn write_ready(&self) -> bool {
+        true
+    }
+    /// is the other end of the file closed?
+    fn hung_up(&self) -> bool {
+        false
+    }
+    /// Wake up `task` once when the file may have become ready
+    ///
+    /// Return false if the file can not tell, then the waiter checks it
+    /// again after a while.
+    fn register_waiter(&self, _task: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+    /// Stop waking up `task`
+    fn unregister_waiter(&self, _task: &Arc<TaskControlBlock>) {}
+    /// is O_NONBLOCK set on the open file?
+    fn nonblocking(&self) -> bool {
+        false
+    }
+    /// set or clear O_NONBLOCK on the open file, shared by its duplicates
+    fn set_nonblocking(&self, _nonblocking: bool) {}
+    /// the capacity of the pipe, None if the file is not a pipe
+    fn pipe_capacity(&self) -> Option<usize> {
+        None
+    }
+    /// change the capacity of the pipe, return false if its data does not fit
+    fn set_pipe_capacity(&self, _capacity: usize) -> bool {
+        false
+    }
+    /// the file as a socket, None if it is not one
+    fn as_socket(&self) -> Option<&Socket> {
+        None
+    }
+    /// the stat of the file, one with no inode on the disk, like an eventfd,
+    /// has no file type
+    fn stat(&self) -> Stat {
+        Stat::new(0, 0, StatMode::NULL, 1, 0)
+    }
 }
 
 /// The stat of a inode
@@ -30,8 +79,24 @@ pub struct Stat {
     pub mode: StatMode,
     /// number of hard links
     pub nlink: u32,
+    /// size of the file in bytes
+    pub size: u64,
     /// unused pad
-    pad: [u64; 7],
+    pad: [u64; 6],
+}
+
+impl Stat {
+    /// Create a stat
+    pub fn new(dev: u64, ino: u64, mode: StatMode, nlink: u32, size: u64) -> Self {
+        Self {
+            dev,
+            ino,
+            mode,
+            nlink,
+            size,
+            pad: [0; 6],
+        }
+    }
 }
 
 bitflags! {
@@ -44,9 +109,21 @@ bitflags! {
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// named pipe
+        const FIFO  = 0o010000;
+        /// character device
+        const CHR   = 0o020000;
+        /// unix-domain socket
+        const SOCK  = 0o140000;
     }
 }
 
-pub use inode::{list_apps, open_file, OSInode, OpenFlags};
-pub use pipe::{make_pipe, Pipe};
+pub use eventfd::EventFd;
+pub use inode::{
+    check_dir, find_socket, join_path, link, list_apps, make_dir, make_fifo, make_socket,
+    normalize_path, open, open_file, remove_dir, unlink, FsError, OSInode, OpenFlags,
+};
+pub use pipe::{forget_fifo, make_pipe, open_fifo, Pipe, PIPE_MAX_SIZE};
+pub use signalfd::SignalFd;
+pub use socket::{unbind_socket, Socket, SocketError, SocketType};
 pub use stdio::{Stdin, Stdout};
@@ -1,101 +1,181 @@
-use super::File;
+use super::{File, FsError, Stat, StatMode};
 use crate::mm::UserBuffer;
 use crate::sync::UPSafeCell;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::{Arc, Weak};
+use lazy_static::*;
 
-use crate::task::suspend_current_and_run_next;
+use crate::task::{
+    block_current_and_run_next, block_current_interruptible, current_add_signal, current_task,
+    wakeup_task, SignalFlags, TaskControlBlock,
+};
+use alloc::vec;
+use alloc::vec::Vec;
 
 /// IPC pipe
 pub struct Pipe {
     readable: bool,
     writable: bool,
+    /// is O_NONBLOCK set?
+    nonblocking: UPSafeCell<bool>,
     buffer: Arc<UPSafeCell<PipeRingBuffer>>,
 }
 
 impl Pipe {
-    /// create readable pipe
-    pub fn read_end_with_buffer(buffer: Arc<UPSafeCell<PipeRingBuffer>>) -> Self {
+    /// create a pipe end on the buffer, a FIFO opened for reading and writing
+    /// is both ends at once
+    fn new(readable: bool, writable: bool, buffer: Arc<UPSafeCell<PipeRingBuffer>>) -> Self {
+        let mut ring_buffer = buffer.exclusive_access();
+        if readable {
+            ring_buffer.readers += 1;
+        }
+        if writable {
+            ring_buffer.writers += 1;
+        }
+        // a FIFO end may be waiting for this end
+        ring_buffer.wake_waiters();
+        drop(ring_buffer);
         Self {
-            readable: true,
-            writable: false,
+            readable,
+            writable,
+            nonblocking: unsafe { UPSafeCell::new(false) },
             buffer,
         }
     }
+    /// create readable pipe
+    pub fn read_end_with_buffer(buffer: Arc<UPSafeCell<PipeRingBuffer>>) -> Self {
+        Self::new(true, false, buffer)
+    }
     /// create writable pipe
     pub fn write_end_with_buffer(buffer: Arc<UPSafeCell<PipeRingBuffer>>) -> Self {
-        Self {
-            readable: false,
-            writable: true,
-            buffer,
-        }
+        Self::new(false, true, buffer)
     }
 }
 
-const RING_BUFFER_SIZE: usize = 32;
-
-#[derive(Copy, Clone, PartialEq)]
-enum RingBufferStatus {
-    Full,
-    Empty,
-    Normal,
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        let mut ring_buffer = self.buffer.exclusive_access();
+        if self.readable {
+            ring_buffer.readers -= 1;
+        }
+        if self.writable {
+            ring_buffer.writers -= 1;
+        }
+        // the other end may see the hang up
+        ring_buffer.wake_readers();
+        ring_buffer.wake_writers();
+    }
 }
 
+/// The default capacity of a pipe
+pub const PIPE_DEFAULT_SIZE: usize = 4096;
+/// The largest capacity a pipe can be set to
+pub const PIPE_MAX_SIZE: usize = 0x10000;
+
 pub struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
+    arr: Vec<u8>,
     head: usize,
-    tail: usize,
-    status: RingBufferStatus,
-    write_end: Option<Weak<Pipe>>,
+    /// the number of bytes in the buffer
+    len: usize,
+    /// the number of open read ends
+    readers: usize,
+    /// the number of open write ends
+    writers: usize,
+    /// tasks waiting for data
+    read_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// tasks waiting for room
+    write_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// tasks polling the pipe or waiting for a FIFO end
+    waiters: Vec<Arc<TaskControlBlock>>,
 }
 
 impl PipeRingBuffer {
     pub fn new() -> Self {
         Self {
-            arr: [0; RING_BUFFER_SIZE],
+            arr: vec![0; PIPE_DEFAULT_SIZE],
             head: 0,
-            tail: 0,
-            status: RingBufferStatus::Empty,
-            write_end: None,
+            len: 0,
+            readers: 0,
+            writers: 0,
+            read_queue: VecDeque::new(),
+            write_queue: VecDeque::new(),
+            waiters: Vec::new(),
         }
     }
-    pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
-        self.write_end = Some(Arc::downgrade(write_end));
+    /// Wake up the tasks polling the pipe
+    fn wake_waiters(&mut self) {
+        for task in self.waiters.drain(..) {
+            wakeup_task(task);
+        }
     }
-    pub fn write_byte(&mut self, byte: u8) {
-        self.status = RingBufferStatus::Normal;
-        self.arr[self.tail] = byte;
-        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
-        if self.tail == self.head {
-            self.status = RingBufferStatus::Full;
+    /// Wake up the readers and pollers, there is data or a hang up
+    fn wake_readers(&mut self) {
+        for task in self.read_queue.drain(..) {
+            wakeup_task(task);
         }
+        self.wake_waiters();
     }
-    pub fn read_byte(&mut self) -> u8 {
-        self.status = RingBufferStatus::Normal;
-        let c = self.arr[self.head];
-        self.head = (self.head + 1) % RING_BUFFER_SIZE;
-        if self.head == self.tail {
-            self.status = RingBufferStatus::Empty;
+    /// Wake up the writers and pollers, there is room or a hang up
+    fn wake_writers(&mut self) {
+        for task in self.write_queue.drain(..) {
+            wakeup_task(task);
         }
-        c
+        self.wake_waiters();
     }
-    pub fn available_read(&self) -> usize {
-        if self.status == RingBufferStatus::Empty {
-            0
-        } else if self.tail > self.head {
-            self.tail - self.head
-        } else {
-            self.tail + RING_BUFFER_SIZE - self.head
+    /// Copy out as much as `buf` holds, return the number of bytes read
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.len);
+        let capacity = self.capacity();
+        // the data may wrap around the end of the array
+        let first = n.min(capacity - self.head);
+        buf[..first].copy_from_slice(&self.arr[self.head..self.head + first]);
+        buf[first..n].copy_from_slice(&self.arr[..n - first]);
+        self.head = (self.head + n) % capacity;
+        self.len -= n;
+        n
+    }
+    /// Copy in as much of `buf` as there is room for, return the number of
+    /// bytes written
+    pub fn write(&mut self, buf: &[u8]) -> usize {
+        let n = buf.len().min(self.available_write());
+        let capacity = self.capacity();
+        let tail = (self.head + self.len) % capacity;
+        // the room may wrap around the end of the array
+        let first = n.min(capacity - tail);
+        self.arr[tail..tail + first].copy_from_slice(&buf[..first]);
+        self.arr[..n - first].copy_from_slice(&buf[first..n]);
+        self.len += n;
+        n
+    }
+    pub fn capacity(&self) -> usize {
+        self.arr.len()
+    }
+    /// Change the capacity, return false if the data does not fit
+    pub fn set_capacity(&mut self, capacity: usize) -> bool {
+        if capacity < self.len {
+            return false;
         }
+        let mut arr = vec![0; capacity];
+        let len = self.len;
+        self.read(&mut arr[..len]);
+        self.arr = arr;
+        self.head = 0;
+        self.len = len;
+        // a grown pipe has room for the writers
+        self.wake_writers();
+        true
+    }
+    pub fn available_read(&self) -> usize {
+        self.len
     }
     pub fn available_write(&self) -> usize {
-        if self.status == RingBufferStatus::Full {
-            0
-        } else {
-            RING_BUFFER_SIZE - self.available_read()
-        }
+        self.capacity() - self.len
+    }
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.readers == 0
     }
     pub fn all_write_ends_closed(&self) -> bool {
-        self.write_end.as_ref().unwrap().upgrade().is_none()
+        self.writers == 0
     }
 }
 
@@ -105,10 +185,56 @@ pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
     let buffer = Arc::new(unsafe { UPSafeCell::new(PipeRingBuffer::new()) });
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
-    buffer.exclusive_access().set_write_end(&write_end);
     (read_end, write_end)
 }
 
+lazy_static! {
+    /// FIFOS: the buffers of the open FIFOs, by inode id
+    static ref FIFOS: UPSafeCell<BTreeMap<u32, Weak<UPSafeCell<PipeRingBuffer>>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Open the FIFO with `inode_id`, the ends opened by all processes share a
+/// buffer
+///
+/// A reader waits until there is a writer and the other way around, unless
+/// the FIFO is opened nonblocking.
+pub fn open_fifo(inode_id: u32, readable: bool, writable: bool, nonblocking: bool) -> Arc<Pipe> {
+    trace!("kernel: open_fifo");
+    let mut fifos = FIFOS.exclusive_access();
+    fifos.retain(|_, buffer| buffer.strong_count() > 0);
+    let buffer = match fifos.get(&inode_id).and_then(Weak::upgrade) {
+        Some(buffer) => buffer,
+        None => {
+            let buffer = Arc::new(unsafe { UPSafeCell::new(PipeRingBuffer::new()) });
+            fifos.insert(inode_id, Arc::downgrade(&buffer));
+            buffer
+        }
+    };
+    drop(fifos);
+    let pipe = Arc::new(Pipe::new(readable, writable, Arc::clone(&buffer)));
+    pipe.set_nonblocking(nonblocking);
+    if nonblocking {
+        return pipe;
+    }
+    let task = current_task().unwrap();
+    loop {
+        let mut ring_buffer = buffer.exclusive_access();
+        if (!readable || ring_buffer.writers > 0) && (!writable || ring_buffer.readers > 0) {
+            return pipe;
+        }
+        ring_buffer.waiters.push(Arc::clone(&task));
+        drop(ring_buffer);
+        block_current_and_run_next();
+    }
+}
+
+/// Forget the buffer of the FIFO with `inode_id` once its inode is dealloced,
+/// so that a new inode with the id does not open it
+pub fn forget_fifo(inode_id: u32) {
+    FIFOS.exclusive_access().remove(&inode_id);
+}
+
 impl File for Pipe {
     fn readable(&self) -> bool {
         self.readable
@@ -116,64 +242,128 @@ impl File for Pipe {
     fn writable(&self) -> bool {
         self.writable
     }
-    fn read(&self, buf: UserBuffer) -> usize {
+    fn read_ready(&self) -> bool {
+        let ring_buffer = self.buffer.exclusive_access();
+        ring_buffer.available_read() > 0 || ring_buffer.all_write_ends_closed()
+    }
+    fn write_ready(&self) -> bool {
+        let ring_buffer = self.buffer.exclusive_access();
+        // a write fails at once with all read ends closed
+        ring_buffer.available_write() > 0 || ring_buffer.all_read_ends_closed()
+    }
+    fn hung_up(&self) -> bool {
+        let ring_buffer = self.buffer.exclusive_access();
+        (self.readable && ring_buffer.all_write_ends_closed())
+            || (self.writable && ring_buffer.all_read_ends_closed())
+    }
+    fn register_waiter(&self, task: &Arc<TaskControlBlock>) -> bool {
+        self.buffer
+            .exclusive_access()
+            .waiters
+            .push(Arc::clone(task));
+        true
+    }
+    fn unregister_waiter(&self, task: &Arc<TaskControlBlock>) {
+        let mut ring_buffer = self.buffer.exclusive_access();
+        ring_buffer
+            .waiters
+            .retain(|waiter| !Arc::ptr_eq(waiter, task));
+        ring_buffer
+            .read_queue
+            .retain(|waiter| !Arc::ptr_eq(waiter, task));
+        ring_buffer
+            .write_queue
+            .retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+    fn nonblocking(&self) -> bool {
+        *self.nonblocking.exclusive_access()
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        *self.nonblocking.exclusive_access() = nonblocking;
+    }
+    fn pipe_capacity(&self) -> Option<usize> {
+        Some(self.buffer.exclusive_access().capacity())
+    }
+    fn set_pipe_capacity(&self, capacity: usize) -> bool {
+        self.buffer.exclusive_access().set_capacity(capacity)
+    }
+    fn stat(&self) -> Stat {
+        Stat::new(0, 0, StatMode::FIFO, 1, 0)
+    }
+    fn read(&self, buf: UserBuffer) -> Result<usize, FsError> {
         trace!("kernel: Pipe::read");
         assert!(self.readable());
-        let want_to_read = buf.len();
-        let mut buf_iter = buf.into_iter();
+        let task = current_task().unwrap();
         let mut already_read = 0usize;
-        loop {
-            let mut ring_buffer = self.buffer.exclusive_access();
-            let loop_read = ring_buffer.available_read();
-            if loop_read == 0 {
-                if ring_buffer.all_write_ends_closed() {
-                    return already_read;
-                }
-                drop(ring_buffer);
-                suspend_current_and_run_next();
-                continue;
-            }
-            for _ in 0..loop_read {
-                if let Some(byte_ref) = buf_iter.next() {
-                    unsafe {
-                        *byte_ref = ring_buffer.read_byte();
+        for dst in buf.buffers {
+            let mut copied = 0;
+            while copied < dst.len() {
+                let mut ring_buffer = self.buffer.exclusive_access();
+                if ring_buffer.available_read() == 0 {
+                    // a nonblocking read returns what it has got
+                    if ring_buffer.all_write_ends_closed() || self.nonblocking() {
+                        return Ok(already_read);
                     }
-                    already_read += 1;
-                    if already_read == want_to_read {
-                        return want_to_read;
+                    ring_buffer.read_queue.push_back(Arc::clone(&task));
+                    drop(ring_buffer);
+                    if !block_current_interruptible() {
+                        // an interrupted read returns what it has got
+                        self.unregister_waiter(&task);
+                        if already_read == 0 {
+                            return Err(FsError::Interrupted);
+                        }
+                        return Ok(already_read);
                     }
-                } else {
-                    return already_read;
+                    continue;
                 }
+                let n = ring_buffer.read(&mut dst[copied..]);
+                copied += n;
+                already_read += n;
+                // there is room for the writers now
+                ring_buffer.wake_writers();
             }
         }
+        Ok(already_read)
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn write(&self, buf: UserBuffer) -> Result<usize, FsError> {
         trace!("kernel: Pipe::write");
         assert!(self.writable());
-        let want_to_write = buf.len();
-        let mut buf_iter = buf.into_iter();
+        let task = current_task().unwrap();
         let mut already_write = 0usize;
-        loop {
-            let mut ring_buffer = self.buffer.exclusive_access();
-            let loop_write = ring_buffer.available_write();
-            if loop_write == 0 {
-                drop(ring_buffer);
-                suspend_current_and_run_next();
-                continue;
-            }
-            // write at most loop_write bytes
-            for _ in 0..loop_write {
-                if let Some(byte_ref) = buf_iter.next() {
-                    ring_buffer.write_byte(unsafe { *byte_ref });
-                    already_write += 1;
-                    if already_write == want_to_write {
-                        return want_to_write;
+        for src in buf.buffers {
+            let mut copied = 0;
+            while copied < src.len() {
+                let mut ring_buffer = self.buffer.exclusive_access();
+                if ring_buffer.all_read_ends_closed() {
+                    // nobody is going to read, the writer gets SIGPIPE
+                    drop(ring_buffer);
+                    current_add_signal(SignalFlags::SIGPIPE);
+                    return Ok(already_write);
+                }
+                if ring_buffer.available_write() == 0 {
+                    // a nonblocking write returns what it has written
+                    if self.nonblocking() {
+                        return Ok(already_write);
+                    }
+                    ring_buffer.write_queue.push_back(Arc::clone(&task));
+                    drop(ring_buffer);
+                    if !block_current_interruptible() {
+                        // an interrupted write returns what it has written
+                        self.unregister_waiter(&task);
+                        if already_write == 0 {
+                            return Err(FsError::Interrupted);
+                        }
+                        return Ok(already_write);
                     }
-                } else {
-                    return already_write;
+                    continue;
                 }
+                let n = ring_buffer.write(&src[copied..]);
+                copied += n;
+                already_write += n;
+                // there is data for the readers now
+                ring_buffer.wake_readers();
             }
         }
+        Ok(already_write)
     }
 }
@@ -0,0 +1,115 @@
+use super::{File, FsError};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::{
+    block_current_interruptible, current_process, current_task, SignalFlags, TaskControlBlock,
+    MAX_SIG,
+};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// The size of a record read from a signalfd, the signal number is in its
+/// first 4 bytes like `ssi_signo` of Linux `signalfd_siginfo`
+pub const SIGNALFD_INFO_SIZE: usize = 128;
+
+/// signalfd, the pending signals in its mask read as records
+///
+/// A read takes the signals from the pending signals of the reading process,
+/// as many as the buffer holds records for, waiting while there are none.
+/// The signals should be blocked, or they may be handled before they are
+/// read.
+pub struct SignalFd {
+    /// the signals to read
+    mask: SignalFlags,
+    /// is O_NONBLOCK set?
+    nonblocking: UPSafeCell<bool>,
+}
+
+impl SignalFd {
+    /// Create a signalfd for the signals in `mask`
+    pub fn new(mask: SignalFlags) -> Self {
+        Self {
+            // SIGKILL and SIGSTOP are never read
+            mask: mask - SignalFlags::UNCATCHABLE,
+            nonblocking: unsafe { UPSafeCell::new(false) },
+        }
+    }
+}
+
+impl File for SignalFd {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut buf: UserBuffer) -> Result<usize, FsError> {
+        trace!("kernel: SignalFd::read");
+        let max_records = buf.len() / SIGNALFD_INFO_SIZE;
+        if max_records == 0 {
+            return Ok(0);
+        }
+        let task = current_task().unwrap();
+        let process = current_process();
+        let mut records = Vec::new();
+        loop {
+            let mut process_inner = process.inner_exclusive_access();
+            let pending = process_inner.signals & self.mask;
+            if pending.is_empty() {
+                if self.nonblocking() {
+                    return Ok(0);
+                }
+                process_inner.signalfd_waiters.push(Arc::clone(&task));
+                drop(process_inner);
+                if !block_current_interruptible() {
+                    self.unregister_waiter(&task);
+                    return Err(FsError::Interrupted);
+                }
+                continue;
+            }
+            for signum in 1..=MAX_SIG {
+                let flag = SignalFlags::from_bits_truncate(1 << signum);
+                if !pending.contains(flag) {
+                    continue;
+                }
+                process_inner.signals.remove(flag);
+                let mut record = [0u8; SIGNALFD_INFO_SIZE];
+                record[..4].copy_from_slice(&(signum as u32).to_ne_bytes());
+                records.extend_from_slice(&record);
+                if records.len() == max_records * SIGNALFD_INFO_SIZE {
+                    break;
+                }
+            }
+            drop(process_inner);
+            return Ok(buf.write_bytes(&records));
+        }
+    }
+    fn write(&self, _buf: UserBuffer) -> Result<usize, FsError> {
+        panic!("Cannot write to signalfd!");
+    }
+    fn read_ready(&self) -> bool {
+        !(current_process().inner_exclusive_access().signals & self.mask).is_empty()
+    }
+    fn register_waiter(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let process = task.process.upgrade().unwrap();
+        process
+            .inner_exclusive_access()
+            .signalfd_waiters
+            .push(Arc::clone(task));
+        true
+    }
+    fn unregister_waiter(&self, task: &Arc<TaskControlBlock>) {
+        if let Some(process) = task.process.upgrade() {
+            process
+                .inner_exclusive_access()
+                .signalfd_waiters
+                .retain(|waiter| !Arc::ptr_eq(waiter, task));
+        }
+    }
+    fn nonblocking(&self) -> bool {
+        *self.nonblocking.exclusive_access()
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        *self.nonblocking.exclusive_access() = nonblocking;
+    }
+}
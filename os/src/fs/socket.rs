@@ -0,0 +1,548 @@
+//! Unix-domain sockets, bound to socket files of easy-fs
+
+use super::{find_socket, make_pipe, make_socket, File, FsError, Pipe, Stat, StatMode};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::{block_current_interruptible, current_task, wakeup_task, TaskControlBlock};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// The most connections waiting to be accepted on a socket
+const SOCKET_MAX_BACKLOG: usize = 16;
+/// The most datagrams queued on a socket
+const SOCKET_MAX_DATAGRAMS: usize = 16;
+/// The longest datagram
+const SOCKET_MAX_DATAGRAM_LEN: usize = 4096;
+
+/// The errors of socket operations
+#[derive(Debug)]
+pub enum SocketError {
+    /// there is a file at the address already
+    AddrInUse,
+    /// there is no socket file at the address
+    NoEntry,
+    /// nobody is bound or listening at the address
+    ConnRefused,
+    /// the socket is not connected
+    NotConnected,
+    /// the socket is connected already
+    IsConnected,
+    /// the socket is not in a state for the operation
+    Invalid,
+    /// the type of the socket does not support the operation
+    OpNotSupp,
+    /// the datagram is too long
+    MsgSize,
+    /// the operation would block on a nonblocking socket
+    WouldBlock,
+    /// a signal interrupts the wait
+    Interrupted,
+    /// the socket file at the address cannot be created
+    Fs(FsError),
+}
+
+/// The type of a socket
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SocketType {
+    /// a connected byte stream
+    Stream,
+    /// messages sent to addresses
+    Datagram,
+}
+
+/// A datagram and the address of its sender
+struct Datagram {
+    from: Option<String>,
+    data: Vec<u8>,
+}
+
+enum SocketState {
+    /// neither listening nor connected
+    Idle,
+    /// a stream socket taking connections
+    Listening {
+        backlog: usize,
+        /// the connected sockets waiting to be accepted
+        pending: VecDeque<Socket>,
+    },
+    /// a connected stream socket, with a pipe in either direction
+    Connected { rx: Arc<Pipe>, tx: Arc<Pipe> },
+}
+
+struct SocketInner {
+    type_: SocketType,
+    /// is the socket closed? a sender may still hold it
+    closed: bool,
+    /// is O_NONBLOCK set?
+    nonblocking: bool,
+    /// the bound address
+    path: Option<String>,
+    state: SocketState,
+    /// the datagrams received
+    datagrams: VecDeque<Datagram>,
+    /// the peer of a connected datagram socket
+    peer: Option<Weak<UPSafeCell<SocketInner>>>,
+    /// tasks waiting for a connection, for a datagram or room for one, or
+    /// polling the socket
+    waiters: Vec<Arc<TaskControlBlock>>,
+}
+
+impl SocketInner {
+    fn wake_waiters(&mut self) {
+        for task in self.waiters.drain(..) {
+            wakeup_task(task);
+        }
+    }
+}
+
+lazy_static! {
+    /// BOUND_SOCKETS: the bound sockets, by the inode id of their socket files
+    static ref BOUND_SOCKETS: UPSafeCell<BTreeMap<u32, Weak<UPSafeCell<SocketInner>>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Forget the socket bound to the socket file with `inode_id` once the inode
+/// is dealloced, so that a new inode with the id does not reach it
+pub fn unbind_socket(inode_id: u32) {
+    BOUND_SOCKETS.exclusive_access().remove(&inode_id);
+}
+
+/// Find the open socket bound to `path`
+fn lookup(path: &str) -> Result<Arc<UPSafeCell<SocketInner>>, SocketError> {
+    let inode_id = find_socket(path).ok_or(SocketError::NoEntry)?;
+    BOUND_SOCKETS
+        .exclusive_access()
+        .get(&inode_id)
+        .and_then(Weak::upgrade)
+        .filter(|socket| !socket.exclusive_access().closed)
+        .ok_or(SocketError::ConnRefused)
+}
+
+/// The state of a stream socket connected through the pipes
+fn connected(rx: Arc<Pipe>, tx: Arc<Pipe>, nonblocking: bool) -> SocketState {
+    // a receive waits for any data and takes what there is, instead of
+    // filling up the buffer like a pipe read
+    rx.set_nonblocking(true);
+    tx.set_nonblocking(nonblocking);
+    SocketState::Connected { rx, tx }
+}
+
+/// Unix-domain socket
+///
+/// A stream socket connects to a listening socket through a pair of pipes,
+/// and a datagram socket queues whole messages on the receiving socket.
+pub struct Socket {
+    inner: Arc<UPSafeCell<SocketInner>>,
+}
+
+impl Socket {
+    /// Create a socket of `type_`
+    pub fn new(type_: SocketType) -> Self {
+        Self::with_state(type_, SocketState::Idle)
+    }
+    fn with_state(type_: SocketType, state: SocketState) -> Self {
+        Self {
+            inner: Arc::new(unsafe {
+                UPSafeCell::new(SocketInner {
+                    type_,
+                    closed: false,
+                    nonblocking: false,
+                    path: None,
+                    state,
+                    datagrams: VecDeque::new(),
+                    peer: None,
+                    waiters: Vec::new(),
+                })
+            }),
+        }
+    }
+    /// Bind the socket to a new socket file at `path`
+    pub fn bind(&self, path: &str) -> Result<(), SocketError> {
+        let mut inner = self.inner.exclusive_access();
+        if inner.path.is_some() {
+            return Err(SocketError::Invalid);
+        }
+        let inode_id = make_socket(path).map_err(|err| match err {
+            FsError::Exists => SocketError::AddrInUse,
+            err => SocketError::Fs(err),
+        })?;
+        let mut bound_sockets = BOUND_SOCKETS.exclusive_access();
+        bound_sockets.retain(|_, socket| socket.strong_count() > 0);
+        bound_sockets.insert(inode_id, Arc::downgrade(&self.inner));
+        inner.path = Some(String::from(path));
+        Ok(())
+    }
+    /// Take connections on the bound stream socket, at most `backlog` of
+    /// them waiting to be accepted
+    pub fn listen(&self, backlog: usize) -> Result<(), SocketError> {
+        let mut inner = self.inner.exclusive_access();
+        if inner.type_ != SocketType::Stream {
+            return Err(SocketError::OpNotSupp);
+        }
+        let backlog = backlog.clamp(1, SOCKET_MAX_BACKLOG);
+        if let SocketState::Listening { backlog: old, .. } = &mut inner.state {
+            *old = backlog;
+            return Ok(());
+        }
+        if !matches!(inner.state, SocketState::Idle) || inner.path.is_none() {
+            return Err(SocketError::Invalid);
+        }
+        inner.state = SocketState::Listening {
+            backlog,
+            pending: VecDeque::new(),
+        };
+        Ok(())
+    }
+    /// Wait for a connection to the listening socket, which is then taken by
+    /// `take_connection`
+    pub fn wait_connection(&self) -> Result<(), SocketError> {
+        let task = current_task().unwrap();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            let pending = match &inner.state {
+                SocketState::Listening { pending, .. } => pending,
+                _ => return Err(SocketError::Invalid),
+            };
+            if !pending.is_empty() {
+                return Ok(());
+            }
+            if inner.nonblocking {
+                return Err(SocketError::WouldBlock);
+            }
+            inner.waiters.push(Arc::clone(&task));
+            drop(inner);
+            if !block_current_interruptible() {
+                self.inner
+                    .exclusive_access()
+                    .waiters
+                    .retain(|waiter| !Arc::ptr_eq(waiter, &task));
+                return Err(SocketError::Interrupted);
+            }
+        }
+    }
+    /// Accept a connection on the listening socket, None if there is none
+    pub fn take_connection(&self) -> Option<Socket> {
+        let mut inner = self.inner.exclusive_access();
+        let socket = match &mut inner.state {
+            SocketState::Listening { pending, .. } => pending.pop_front()?,
+            _ => return None,
+        };
+        // there is room for the connecting sockets now
+        inner.wake_waiters();
+        Some(socket)
+    }
+    /// Connect the socket to the socket bound to `path`
+    ///
+    /// A stream socket is connected once the listening socket queues the
+    /// connection, waiting while its backlog is full. A datagram socket
+    /// just sends to it by default.
+    pub fn connect(&self, path: &str) -> Result<(), SocketError> {
+        let target = lookup(path)?;
+        let type_ = self.inner.exclusive_access().type_;
+        if type_ == SocketType::Datagram {
+            // a datagram socket may send to itself
+            if !Arc::ptr_eq(&target, &self.inner)
+                && target.exclusive_access().type_ != SocketType::Datagram
+            {
+                return Err(SocketError::ConnRefused);
+            }
+            self.inner.exclusive_access().peer = Some(Arc::downgrade(&target));
+            return Ok(());
+        }
+        if Arc::ptr_eq(&target, &self.inner) {
+            return Err(SocketError::ConnRefused);
+        }
+        let task = current_task().unwrap();
+        loop {
+            let inner = self.inner.exclusive_access();
+            let nonblocking = match inner.state {
+                SocketState::Idle => inner.nonblocking,
+                SocketState::Connected { .. } => return Err(SocketError::IsConnected),
+                SocketState::Listening { .. } => return Err(SocketError::Invalid),
+            };
+            drop(inner);
+            let mut target_inner = target.exclusive_access();
+            let (backlog, pending) = match &mut target_inner.state {
+                SocketState::Listening { backlog, pending } => (*backlog, pending),
+                _ => return Err(SocketError::ConnRefused),
+            };
+            if pending.len() < backlog {
+                let (rx, peer_tx) = make_pipe();
+                let (peer_rx, tx) = make_pipe();
+                pending.push_back(Socket::with_state(
+                    SocketType::Stream,
+                    connected(peer_rx, peer_tx, false),
+                ));
+                target_inner.wake_waiters();
+                drop(target_inner);
+                self.inner.exclusive_access().state = connected(rx, tx, nonblocking);
+                return Ok(());
+            }
+            if nonblocking {
+                return Err(SocketError::WouldBlock);
+            }
+            target_inner.waiters.push(Arc::clone(&task));
+            drop(target_inner);
+            if !block_current_interruptible() {
+                target
+                    .exclusive_access()
+                    .waiters
+                    .retain(|waiter| !Arc::ptr_eq(waiter, &task));
+                return Err(SocketError::Interrupted);
+            }
+        }
+    }
+    /// Send `buf`, to the socket bound to `path` if it is given, return the
+    /// number of bytes sent
+    ///
+    /// A datagram is sent whole, waiting while the queue of the receiving
+    /// socket is full.
+    pub fn send_to(&self, buf: UserBuffer, path: Option<&str>) -> Result<usize, SocketError> {
+        let inner = self.inner.exclusive_access();
+        if inner.type_ == SocketType::Stream {
+            let tx = match &inner.state {
+                SocketState::Connected { .. } if path.is_some() => {
+                    return Err(SocketError::IsConnected)
+                }
+                SocketState::Connected { tx, .. } => Arc::clone(tx),
+                _ => return Err(SocketError::NotConnected),
+            };
+            drop(inner);
+            if tx.nonblocking() && !tx.write_ready() {
+                return Err(SocketError::WouldBlock);
+            }
+            // a pipe fails only when a signal interrupts it
+            return tx.write(buf).map_err(|_| SocketError::Interrupted);
+        }
+        let from = inner.path.clone();
+        let nonblocking = inner.nonblocking;
+        let peer = inner.peer.clone();
+        drop(inner);
+        let target = match path {
+            Some(path) => lookup(path)?,
+            None => peer
+                .ok_or(SocketError::NotConnected)?
+                .upgrade()
+                .ok_or(SocketError::ConnRefused)?,
+        };
+        if buf.len() > SOCKET_MAX_DATAGRAM_LEN {
+            return Err(SocketError::MsgSize);
+        }
+        let mut data = vec![0u8; buf.len()];
+        buf.read_bytes(&mut data);
+        let task = current_task().unwrap();
+        loop {
+            let mut target_inner = target.exclusive_access();
+            if target_inner.closed || target_inner.type_ != SocketType::Datagram {
+                return Err(SocketError::ConnRefused);
+            }
+            if target_inner.datagrams.len() < SOCKET_MAX_DATAGRAMS {
+                let len = data.len();
+                target_inner.datagrams.push_back(Datagram { from, data });
+                target_inner.wake_waiters();
+                return Ok(len);
+            }
+            if nonblocking {
+                return Err(SocketError::WouldBlock);
+            }
+            target_inner.waiters.push(Arc::clone(&task));
+            drop(target_inner);
+            if !block_current_interruptible() {
+                target
+                    .exclusive_access()
+                    .waiters
+                    .retain(|waiter| !Arc::ptr_eq(waiter, &task));
+                return Err(SocketError::Interrupted);
+            }
+        }
+    }
+    /// Receive into `buf`, return the number of bytes received, and the
+    /// address of the sender of a datagram if it is bound
+    ///
+    /// A receive waits for any data. The rest of a datagram longer than
+    /// `buf` is discarded.
+    pub fn recv_from(&self, mut buf: UserBuffer) -> Result<(usize, Option<String>), SocketError> {
+        let task = current_task().unwrap();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.type_ == SocketType::Stream {
+                let rx = match &inner.state {
+                    SocketState::Connected { rx, .. } => Arc::clone(rx),
+                    _ => return Err(SocketError::NotConnected),
+                };
+                let nonblocking = inner.nonblocking;
+                drop(inner);
+                // there is data, or the peer has closed its end
+                if rx.read_ready() {
+                    return rx
+                        .read(buf)
+                        .map(|len| (len, None))
+                        .map_err(|_| SocketError::Interrupted);
+                }
+                if nonblocking {
+                    return Err(SocketError::WouldBlock);
+                }
+                rx.register_waiter(&task);
+                let woken = block_current_interruptible();
+                rx.unregister_waiter(&task);
+                if !woken {
+                    return Err(SocketError::Interrupted);
+                }
+                continue;
+            }
+            if let Some(datagram) = inner.datagrams.pop_front() {
+                // there is room for the senders now
+                inner.wake_waiters();
+                drop(inner);
+                let len = buf.write_bytes(&datagram.data);
+                return Ok((len, datagram.from));
+            }
+            if inner.nonblocking {
+                return Err(SocketError::WouldBlock);
+            }
+            inner.waiters.push(Arc::clone(&task));
+            drop(inner);
+            if !block_current_interruptible() {
+                self.inner
+                    .exclusive_access()
+                    .waiters
+                    .retain(|waiter| !Arc::ptr_eq(waiter, &task));
+                return Err(SocketError::Interrupted);
+            }
+        }
+    }
+    /// The peer of a connected datagram socket, unless it is the socket
+    /// itself
+    fn other_peer(&self) -> Option<Arc<UPSafeCell<SocketInner>>> {
+        let peer = self
+            .inner
+            .exclusive_access()
+            .peer
+            .as_ref()
+            .and_then(Weak::upgrade)?;
+        if Arc::ptr_eq(&peer, &self.inner) {
+            None
+        } else {
+            Some(peer)
+        }
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.closed = true;
+        // hang up the connection, or refuse the pending ones
+        inner.state = SocketState::Idle;
+        inner.datagrams.clear();
+        // the senders waiting for room are refused
+        inner.wake_waiters();
+    }
+}
+
+impl File for Socket {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, buf: UserBuffer) -> Result<usize, FsError> {
+        trace!("kernel: Socket::read");
+        match self.recv_from(buf) {
+            Ok((len, _)) => Ok(len),
+            Err(SocketError::Interrupted) => Err(FsError::Interrupted),
+            Err(_) => Ok(0),
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> Result<usize, FsError> {
+        trace!("kernel: Socket::write");
+        match self.send_to(buf, None) {
+            Ok(len) => Ok(len),
+            Err(SocketError::Interrupted) => Err(FsError::Interrupted),
+            Err(_) => Ok(0),
+        }
+    }
+    fn read_ready(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        match &inner.state {
+            SocketState::Listening { pending, .. } => !pending.is_empty(),
+            SocketState::Connected { rx, .. } => rx.read_ready(),
+            SocketState::Idle => !inner.datagrams.is_empty(),
+        }
+    }
+    fn write_ready(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        if let SocketState::Connected { tx, .. } = &inner.state {
+            return tx.write_ready();
+        }
+        if inner.type_ == SocketType::Stream {
+            return false;
+        }
+        let sending_to_self =
+            matches!(&inner.peer, Some(peer) if peer.as_ptr() == Arc::as_ptr(&self.inner));
+        if sending_to_self {
+            return inner.datagrams.len() < SOCKET_MAX_DATAGRAMS;
+        }
+        drop(inner);
+        // a datagram socket without a peer sends anywhere
+        self.other_peer().map_or(true, |peer| {
+            peer.exclusive_access().datagrams.len() < SOCKET_MAX_DATAGRAMS
+        })
+    }
+    fn hung_up(&self) -> bool {
+        match &self.inner.exclusive_access().state {
+            SocketState::Connected { rx, .. } => rx.hung_up(),
+            _ => false,
+        }
+    }
+    fn register_waiter(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        if let SocketState::Connected { rx, tx } = &inner.state {
+            rx.register_waiter(task);
+            tx.register_waiter(task);
+            return true;
+        }
+        inner.waiters.push(Arc::clone(task));
+        drop(inner);
+        // the peer wakes us up when there is room for a datagram
+        if let Some(peer) = self.other_peer() {
+            peer.exclusive_access().waiters.push(Arc::clone(task));
+        }
+        true
+    }
+    fn unregister_waiter(&self, task: &Arc<TaskControlBlock>) {
+        let mut inner = self.inner.exclusive_access();
+        if let SocketState::Connected { rx, tx } = &inner.state {
+            rx.unregister_waiter(task);
+            tx.unregister_waiter(task);
+        }
+        inner.waiters.retain(|waiter| !Arc::ptr_eq(waiter, task));
+        drop(inner);
+        if let Some(peer) = self.other_peer() {
+            peer.exclusive_access()
+                .waiters
+                .retain(|waiter| !Arc::ptr_eq(waiter, task));
+        }
+    }
+    fn nonblocking(&self) -> bool {
+        self.inner.exclusive_access().nonblocking
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        let mut inner = self.inner.exclusive_access();
+        inner.nonblocking = nonblocking;
+        if let SocketState::Connected { tx, .. } = &inner.state {
+            tx.set_nonblocking(nonblocking);
+        }
+    }
+    fn as_socket(&self) -> Option<&Socket> {
+        Some(self)
+    }
+    fn stat(&self) -> Stat {
+        Stat::new(0, 0, StatMode::SOCK, 1, 0)
+    }
+}
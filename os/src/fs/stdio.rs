@@ -1,10 +1,38 @@
-use super::File;
+use super::{File, FsError, Stat, StatMode};
 use crate::mm::UserBuffer;
 use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use crate::sync::UPSafeCell;
+use crate::task::{suspend_current_and_run_next, TaskControlBlock};
+use alloc::sync::Arc;
+use lazy_static::*;
+
+lazy_static! {
+    /// A char taken from the console to see if stdin is ready, but not read yet
+    static ref STDIN_PENDING: UPSafeCell<Option<u8>> = unsafe { UPSafeCell::new(None) };
+}
+
+/// Take a char from the console if there is one
+fn console_try_getchar() -> Option<u8> {
+    match console_getchar() {
+        0 => None,
+        c => Some(c as u8),
+    }
+}
 
 /// stdin file for getting chars from console
-pub struct Stdin;
+pub struct Stdin {
+    /// is O_NONBLOCK set?
+    nonblocking: UPSafeCell<bool>,
+}
+
+impl Stdin {
+    /// Create a new stdin
+    pub fn new() -> Self {
+        Self {
+            nonblocking: unsafe { UPSafeCell::new(false) },
+        }
+    }
+}
 
 /// stdout file for putting chars to console
 pub struct Stdout;
@@ -16,28 +44,44 @@ impl File for Stdin {
     fn writable(&self) -> bool {
         false
     }
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
+    fn read(&self, mut user_buf: UserBuffer) -> Result<usize, FsError> {
         assert_eq!(user_buf.len(), 1);
         // busy loop
-        let mut c: usize;
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
+        let ch = loop {
+            let pending = STDIN_PENDING.exclusive_access().take();
+            if let Some(ch) = pending.or_else(console_try_getchar) {
+                break ch;
             }
-        }
-        let ch = c as u8;
+            suspend_current_and_run_next();
+        };
         unsafe {
             user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
         }
-        1
+        Ok(1)
     }
-    fn write(&self, _user_buf: UserBuffer) -> usize {
+    fn write(&self, _user_buf: UserBuffer) -> Result<usize, FsError> {
         panic!("Cannot write to stdin!");
     }
+    fn read_ready(&self) -> bool {
+        let mut pending = STDIN_PENDING.exclusive_access();
+        if pending.is_none() {
+            *pending = console_try_getchar();
+        }
+        pending.is_some()
+    }
+    fn register_waiter(&self, _task: &Arc<TaskControlBlock>) -> bool {
+        // the console is polled, there is no interrupt to wake up the waiter
+        false
+    }
+    fn nonblocking(&self) -> bool {
+        *self.nonblocking.exclusive_access()
+    }
+    fn set_nonblocking(&self, nonblocking: bool) {
+        *self.nonblocking.exclusive_access() = nonblocking;
+    }
+    fn stat(&self) -> Stat {
+        Stat::new(0, 0, StatMode::CHR, 1, 0)
+    }
 }
 
 impl File for Stdout {
@@ -47,13 +91,16 @@ impl File for Stdout {
     fn writable(&self) -> bool {
         true
     }
-    fn read(&self, _user_buf: UserBuffer) -> usize {
+    fn read(&self, _user_buf: UserBuffer) -> Result<usize, FsError> {
         panic!("Cannot read from stdout!");
     }
-    fn write(&self, user_buf: UserBuffer) -> usize {
+    fn write(&self, user_buf: UserBuffer) -> Result<usize, FsError> {
         for buffer in user_buf.buffers.iter() {
             print!("{}", core::str::from_utf8(*buffer).unwrap());
         }
-        user_buf.len()
+        Ok(user_buf.len())
+    }
+    fn stat(&self) -> Stat {
+        Stat::new(0, 0, StatMode::CHR, 1, 0)
     }
 }
@@ -43,6 +43,29 @@ pub struct MemorySet {
     pub page_table: PageTable,
     /// areas
     pub areas: Vec<MapArea>,
+    /// tls image of the program, copied into the tls block of every thread
+    pub tls: Option<TlsTemplate>,
+}
+
+/// Thread-local storage image described by the PT_TLS program header
+#[derive(Copy, Clone, Debug)]
+pub struct TlsTemplate {
+    /// start address of the initialized image(.tdata) in user space
+    pub start_va: usize,
+    /// size of the initialized image
+    pub file_size: usize,
+    /// size of the whole tls block, including the zeroed part(.tbss)
+    pub mem_size: usize,
+    /// alignment of the tls block
+    pub align: usize,
+}
+
+impl TlsTemplate {
+    /// Pages taken by the tls block at the top of a user stack
+    pub fn reserved_size(&self) -> usize {
+        let size = self.mem_size + self.align.max(core::mem::size_of::<usize>()) - 1;
+        (size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+    }
 }
 
 impl MemorySet {
@@ -51,6 +74,7 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            tls: None,
         }
     }
     /// Get he page table token
@@ -84,6 +108,42 @@ impl MemorySet {
             }
         }
     }
+    /// remove the user area exactly covering [start_vpn, end_vpn)
+    pub fn remove_user_area(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        if self.areas.iter().any(|area| {
+            area.vpn_range.get_start() == start_vpn
+                && area.vpn_range.get_end() == end_vpn
+                && area.map_perm.contains(MapPermission::U)
+        }) {
+            self.remove_area_with_start_vpn(start_vpn);
+            true
+        } else {
+            false
+        }
+    }
+    /// Is any page in [start_vpn, end_vpn) mapped?
+    pub fn is_mapped(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        VPNRange::new(start_vpn, end_vpn).into_iter().any(|vpn| {
+            self.page_table
+                .translate(vpn)
+                .map_or(false, |pte| pte.is_valid())
+        })
+    }
+    /// Is every page in [start_vpn, end_vpn) mapped?
+    pub fn is_all_mapped(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        VPNRange::new(start_vpn, end_vpn).into_iter().all(|vpn| {
+            self.page_table
+                .translate(vpn)
+                .map_or(false, |pte| pte.is_valid())
+        })
+    }
+    /// Size of all areas in bytes, trampoline excluded
+    pub fn mapped_size(&self) -> usize {
+        self.areas
+            .iter()
+            .map(|area| (area.vpn_range.get_end().0 - area.vpn_range.get_start().0) * PAGE_SIZE)
+            .sum()
+    }
     /// Add a new MapArea into this MemorySet.
     /// Assuming that there are no conflicts in the virtual address
     /// space.
@@ -214,6 +274,14 @@ impl MemorySet {
                     map_area,
                     Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
                 );
+            } else if ph.get_type().unwrap() == xmas_elf::program::Type::Tls {
+                // the image itself lies in a load segment, only remember where it is
+                memory_set.tls = Some(TlsTemplate {
+                    start_va: ph.virtual_addr() as usize,
+                    file_size: ph.file_size() as usize,
+                    mem_size: ph.mem_size() as usize,
+                    align: ph.align() as usize,
+                });
             }
         }
         // map user stack with U flags
@@ -229,6 +297,7 @@ impl MemorySet {
     /// Create a new address space by copy code&data from a exited process's address space.
     pub fn from_existed_user(user_space: &Self) -> Self {
         let mut memory_set = Self::new_bare();
+        memory_set.tls = user_space.tls;
         // map trampoline
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
@@ -217,6 +217,32 @@ pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
         .get_mut()
 }
 
+/// copy `value` to `ptr` in other address space, the content pointed to by
+/// `ptr` may cross physical pages
+pub fn copy_to_user<T>(token: usize, ptr: *mut T, value: &T) {
+    let len = core::mem::size_of::<T>();
+    let src = unsafe { core::slice::from_raw_parts(value as *const T as *const u8, len) };
+    let mut copied = 0;
+    for dst in translated_byte_buffer(token, ptr as *const u8, len) {
+        dst.copy_from_slice(&src[copied..copied + dst.len()]);
+        copied += dst.len();
+    }
+}
+
+/// copy a value from `ptr` in other address space, the content pointed to by
+/// `ptr` may cross physical pages
+pub fn copy_from_user<T: Copy>(token: usize, ptr: *const T) -> T {
+    let len = core::mem::size_of::<T>();
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let dst = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, len) };
+    let mut copied = 0;
+    for src in translated_byte_buffer(token, ptr as *const u8, len) {
+        dst[copied..copied + src.len()].copy_from_slice(src);
+        copied += src.len();
+    }
+    unsafe { value.assume_init() }
+}
+
 /// An abstraction over a buffer passed from user space to kernel space
 pub struct UserBuffer {
     /// A list of buffers
@@ -236,6 +262,26 @@ impl UserBuffer {
         }
         total
     }
+    /// Copy `data` into the buffer, return the number of bytes copied
+    pub fn write_bytes(&mut self, data: &[u8]) -> usize {
+        let mut copied = 0;
+        for buffer in self.buffers.iter_mut() {
+            let len = buffer.len().min(data.len() - copied);
+            buffer[..len].copy_from_slice(&data[copied..copied + len]);
+            copied += len;
+        }
+        copied
+    }
+    /// Copy the buffer into `data`, return the number of bytes copied
+    pub fn read_bytes(&self, data: &mut [u8]) -> usize {
+        let mut copied = 0;
+        for buffer in self.buffers.iter() {
+            let len = buffer.len().min(data.len() - copied);
+            data[copied..copied + len].copy_from_slice(&buffer[..len]);
+            copied += len;
+        }
+        copied
+    }
 }
 
 impl IntoIterator for UserBuffer {
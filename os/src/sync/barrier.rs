@@ -0,0 +1,59 @@
+//! Barrier
+
+use super::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::{collections::VecDeque, sync::Arc};
+
+/// Barrier structure, releases the waiting threads once `count` of them arrive
+pub struct Barrier {
+    inner: UPSafeCell<BarrierInner>,
+}
+
+pub struct BarrierInner {
+    /// the number of threads to wait for
+    count: usize,
+    /// the number of threads arrived in the current round
+    arrived: usize,
+    wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Barrier {
+    /// Create a new barrier for `count` threads
+    pub fn new(count: usize) -> Self {
+        trace!("kernel: Barrier::new");
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(BarrierInner {
+                    count,
+                    arrived: 0,
+                    wait_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// wait until all threads arrive, return true for the last one to arrive
+    pub fn wait(&self) -> bool {
+        trace!("kernel: Barrier::wait");
+        let mut inner = self.inner.exclusive_access();
+        inner.arrived += 1;
+        if inner.arrived == inner.count {
+            // start the next round and release this one
+            inner.arrived = 0;
+            for task in inner.wait_queue.drain(..) {
+                wakeup_task(task);
+            }
+            true
+        } else {
+            inner.wait_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+            false
+        }
+    }
+
+    /// Is nobody waiting at the barrier?
+    pub fn is_idle(&self) -> bool {
+        self.inner.exclusive_access().wait_queue.is_empty()
+    }
+}
@@ -1,7 +1,8 @@
 //! Conditian variable
 
 use crate::sync::{Mutex, UPSafeCell};
-use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use crate::task::{block_current_interruptible, current_task, wakeup_task, TaskControlBlock};
+use crate::timer::{add_timer, remove_timer};
 use alloc::{collections::VecDeque, sync::Arc};
 
 /// Condition variable structure
@@ -35,14 +36,49 @@ impl Condvar {
         }
     }
 
-    /// blocking current task, let it wait on the condition variable
-    pub fn wait(&self, mutex: Arc<dyn Mutex>) {
+    /// Signal all tasks waiting on the condition variable
+    pub fn broadcast(&self) {
+        let mut inner = self.inner.exclusive_access();
+        for task in inner.wait_queue.drain(..) {
+            wakeup_task(task);
+        }
+    }
+
+    /// Is nobody waiting on the condition variable?
+    pub fn is_idle(&self) -> bool {
+        self.inner.exclusive_access().wait_queue.is_empty()
+    }
+
+    /// blocking current task, let it wait on the condition variable until
+    /// `expire_ms` if given, or until a signal interrupts the wait
+    ///
+    /// The mutex is locked again in either case, return false if the task
+    /// is not signalled.
+    pub fn wait(&self, mutex: Arc<dyn Mutex>, expire_ms: Option<usize>) -> bool {
         trace!("kernel: Condvar::wait_with_mutex");
         mutex.unlock();
+        let task = current_task().unwrap();
+        let mut inner = self.inner.exclusive_access();
+        inner.wait_queue.push_back(Arc::clone(&task));
+        drop(inner);
+        if let Some(expire_ms) = expire_ms {
+            add_timer(expire_ms, Arc::clone(&task));
+        }
+        block_current_interruptible();
+        if expire_ms.is_some() {
+            remove_timer(Arc::clone(&task));
+        }
+        // still queued means it is the timer or a signal who woke us up
         let mut inner = self.inner.exclusive_access();
-        inner.wait_queue.push_back(current_task().unwrap());
+        let signalled = match inner.wait_queue.iter().position(|t| Arc::ptr_eq(t, &task)) {
+            Some(pos) => {
+                inner.wait_queue.remove(pos);
+                false
+            }
+            None => true,
+        };
         drop(inner);
-        block_current_and_run_next();
         mutex.lock();
+        signalled
     }
 }
@@ -0,0 +1,95 @@
+//! Futex wait queues
+//!
+//! Waiters are keyed by the physical address of the futex word, so that the
+//! key does not depend on where the word is mapped. The queues are hashed into
+//! a fixed number of buckets.
+
+use crate::sync::UPSafeCell;
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// The number of hash buckets of futex wait queues
+const FUTEX_BUCKETS: usize = 64;
+
+/// A task waiting on a futex word
+struct FutexWaiter {
+    /// physical address of the futex word
+    pa: usize,
+    /// the waiting task
+    task: Arc<TaskControlBlock>,
+}
+
+lazy_static! {
+    /// FUTEX_QUEUES: the hashed wait queues of all futexes
+    static ref FUTEX_QUEUES: Vec<UPSafeCell<VecDeque<FutexWaiter>>> = (0..FUTEX_BUCKETS)
+        .map(|_| unsafe { UPSafeCell::new(VecDeque::new()) })
+        .collect();
+}
+
+/// The bucket of a futex word
+fn bucket(pa: usize) -> &'static UPSafeCell<VecDeque<FutexWaiter>> {
+    // futex words are 4 bytes aligned, drop the bits always zero
+    &FUTEX_QUEUES[(pa >> 2) % FUTEX_BUCKETS]
+}
+
+/// Queue `task` on the futex word at `pa`, the caller blocks it afterwards
+pub fn futex_enqueue(pa: usize, task: Arc<TaskControlBlock>) {
+    bucket(pa)
+        .exclusive_access()
+        .push_back(FutexWaiter { pa, task });
+}
+
+/// Remove `task` from any futex it waits on, it may have been requeued
+///
+/// Return false if it is not queued, i.e. it has been woken up.
+pub fn futex_remove_task(task: &Arc<TaskControlBlock>) -> bool {
+    let mut removed = false;
+    for queue in FUTEX_QUEUES.iter() {
+        let mut queue = queue.exclusive_access();
+        let len = queue.len();
+        queue.retain(|waiter| !Arc::ptr_eq(&waiter.task, task));
+        removed |= queue.len() != len;
+    }
+    removed
+}
+
+/// Take at most `count` waiters of the futex word at `pa` in FIFO order
+fn take_waiters(pa: usize, count: usize) -> Vec<Arc<TaskControlBlock>> {
+    let mut queue = bucket(pa).exclusive_access();
+    let mut taken = Vec::new();
+    let mut i = 0;
+    while i < queue.len() && taken.len() < count {
+        if queue[i].pa == pa {
+            taken.push(queue.remove(i).unwrap().task);
+        } else {
+            i += 1;
+        }
+    }
+    taken
+}
+
+/// Wake up at most `count` waiters of the futex word at `pa`, return the
+/// number of tasks woken up
+pub fn futex_wake(pa: usize, count: usize) -> usize {
+    let waiters = take_waiters(pa, count);
+    let woken = waiters.len();
+    for task in waiters {
+        wakeup_task(task);
+    }
+    woken
+}
+
+/// Wake up at most `wake_count` waiters of the futex word at `pa`, and move
+/// at most `requeue_count` of the rest to the futex word at `pa2`
+///
+/// Return the number of tasks woken up.
+pub fn futex_requeue(pa: usize, wake_count: usize, pa2: usize, requeue_count: usize) -> usize {
+    let woken = futex_wake(pa, wake_count);
+    for task in take_waiters(pa, requeue_count) {
+        futex_enqueue(pa2, task);
+    }
+    woken
+}
@@ -1,11 +1,19 @@
 //! Synchronization and interior mutability primitives
 
+mod barrier;
 mod condvar;
+mod futex;
+mod msg_queue;
 mod mutex;
+mod rwlock;
 mod semaphore;
 mod up;
 
+pub use barrier::Barrier;
 pub use condvar::Condvar;
-pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use futex::{futex_enqueue, futex_remove_task, futex_requeue, futex_wake};
+pub use msg_queue::{msg_queue, msg_queue_get, msg_queue_remove, MsgError, MsgQueue, MSGMAX};
+pub use mutex::{update_priority, Mutex, MutexBlocking, MutexBlockingInner, MutexSpin};
+pub use rwlock::{RwLock, RWLOCK_MAX_READERS};
 pub use semaphore::Semaphore;
 pub use up::UPSafeCell;
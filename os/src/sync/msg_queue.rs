@@ -0,0 +1,295 @@
+//! System V message queues
+//!
+//! Queues are kernel-wide. A queue is created by a key, or private with
+//! IPC_PRIVATE, and is used by its id until it is removed.
+//!
+//! An id is the slot of the queue plus `MSGMNI` times the sequence number of
+//! the slot, which goes up when the queue is removed, so the id of a removed
+//! queue does not find the next queue in its slot.
+
+use crate::sync::UPSafeCell;
+use crate::task::{block_current_interruptible, current_task, wakeup_task, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// The key of a queue nobody else finds
+pub const IPC_PRIVATE: usize = 0;
+/// Create the queue if the key has none
+pub const IPC_CREAT: usize = 0o1000;
+/// Fail if the key has a queue already
+pub const IPC_EXCL: usize = 0o2000;
+/// Fail instead of waiting
+pub const IPC_NOWAIT: usize = 0o4000;
+/// Truncate a message longer than the buffer
+pub const MSG_NOERROR: usize = 0o10000;
+/// Receive the first message not of the type given
+pub const MSG_EXCEPT: usize = 0o20000;
+/// The longest message text
+pub const MSGMAX: usize = 8192;
+/// The most bytes of texts queued on a queue
+const MSGMNB: usize = 16384;
+/// The most queues
+const MSGMNI: usize = 32;
+/// The sequence number of a slot wraps at this, an id fits in an `i32`
+const MSG_SEQ_MAX: usize = (i32::MAX as usize + 1) / MSGMNI;
+
+/// The errors of message queue operations
+#[derive(Debug)]
+pub enum MsgError {
+    /// the key has a queue already
+    Exists,
+    /// the key has no queue
+    NotFound,
+    /// there are too many queues
+    NoSpace,
+    /// there is no message of the type
+    NoMessage,
+    /// the queue is full
+    WouldBlock,
+    /// the message is longer than the buffer
+    TooBig,
+    /// the queue is removed
+    Removed,
+    /// a signal interrupts the wait
+    Interrupted,
+}
+
+/// A message and its type
+struct Message {
+    mtype: usize,
+    text: Vec<u8>,
+}
+
+/// Message queue structure
+pub struct MsgQueue {
+    /// the key the queue is found by, IPC_PRIVATE for none
+    key: usize,
+    inner: UPSafeCell<MsgQueueInner>,
+}
+
+struct MsgQueueInner {
+    messages: VecDeque<Message>,
+    /// the bytes of the texts queued
+    bytes: usize,
+    /// the queue is removed, the waiters fail
+    removed: bool,
+    /// tasks waiting for room
+    send_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// tasks waiting for a message
+    recv_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl MsgQueue {
+    /// Create a new message queue
+    fn new(key: usize) -> Self {
+        trace!("kernel: MsgQueue::new");
+        Self {
+            key,
+            inner: unsafe {
+                UPSafeCell::new(MsgQueueInner {
+                    messages: VecDeque::new(),
+                    bytes: 0,
+                    removed: false,
+                    send_queue: VecDeque::new(),
+                    recv_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// Queue a message of `mtype`, waiting for room unless `msgflg` has
+    /// IPC_NOWAIT
+    pub fn send(&self, mtype: usize, text: Vec<u8>, msgflg: usize) -> Result<(), MsgError> {
+        trace!("kernel: MsgQueue::send");
+        let task = current_task().unwrap();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.removed {
+                return Err(MsgError::Removed);
+            }
+            // a message fits an empty queue anyway
+            if inner.messages.is_empty() || inner.bytes + text.len() <= MSGMNB {
+                inner.bytes += text.len();
+                inner.messages.push_back(Message { mtype, text });
+                // the receivers look for their types again
+                for task in inner.recv_queue.drain(..) {
+                    wakeup_task(task);
+                }
+                return Ok(());
+            }
+            if msgflg & IPC_NOWAIT != 0 {
+                return Err(MsgError::WouldBlock);
+            }
+            inner.send_queue.push_back(Arc::clone(&task));
+            drop(inner);
+            if !block_current_interruptible() {
+                self.inner
+                    .exclusive_access()
+                    .send_queue
+                    .retain(|waiter| !Arc::ptr_eq(waiter, &task));
+                return Err(MsgError::Interrupted);
+            }
+        }
+    }
+
+    /// Take a message selected by `msgtyp`, waiting for one unless `msgflg`
+    /// has IPC_NOWAIT
+    ///
+    /// With `msgtyp` 0 the first message is taken, with a positive one the
+    /// first message of that type, or with MSG_EXCEPT of another type, and
+    /// with a negative one the first message of the lowest type not above
+    /// its absolute value. A text longer than `max_len` fails unless
+    /// `msgflg` has MSG_NOERROR, then it is truncated.
+    pub fn receive(
+        &self,
+        msgtyp: isize,
+        max_len: usize,
+        msgflg: usize,
+    ) -> Result<(usize, Vec<u8>), MsgError> {
+        trace!("kernel: MsgQueue::receive");
+        let task = current_task().unwrap();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.removed {
+                return Err(MsgError::Removed);
+            }
+            let pos = match msgtyp {
+                0 => (!inner.messages.is_empty()).then_some(0),
+                msgtyp if msgtyp > 0 => {
+                    let except = msgflg & MSG_EXCEPT != 0;
+                    inner
+                        .messages
+                        .iter()
+                        .position(|m| (m.mtype == msgtyp as usize) != except)
+                }
+                msgtyp => inner
+                    .messages
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, m)| m.mtype <= msgtyp.unsigned_abs())
+                    .min_by_key(|(_, m)| m.mtype)
+                    .map(|(pos, _)| pos),
+            };
+            if let Some(pos) = pos {
+                if inner.messages[pos].text.len() > max_len && msgflg & MSG_NOERROR == 0 {
+                    return Err(MsgError::TooBig);
+                }
+                let Message { mtype, mut text } = inner.messages.remove(pos).unwrap();
+                inner.bytes -= text.len();
+                text.truncate(max_len);
+                for task in inner.send_queue.drain(..) {
+                    wakeup_task(task);
+                }
+                return Ok((mtype, text));
+            }
+            if msgflg & IPC_NOWAIT != 0 {
+                return Err(MsgError::NoMessage);
+            }
+            inner.recv_queue.push_back(Arc::clone(&task));
+            drop(inner);
+            if !block_current_interruptible() {
+                self.inner
+                    .exclusive_access()
+                    .recv_queue
+                    .retain(|waiter| !Arc::ptr_eq(waiter, &task));
+                return Err(MsgError::Interrupted);
+            }
+        }
+    }
+
+    /// Remove the queue, its waiters fail
+    fn remove(&self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.removed = true;
+        inner.messages.clear();
+        inner.bytes = 0;
+        for task in inner.send_queue.drain(..) {
+            wakeup_task(task);
+        }
+        for task in inner.recv_queue.drain(..) {
+            wakeup_task(task);
+        }
+    }
+}
+
+/// A slot of the message queue table
+struct MsgQueueSlot {
+    /// the sequence number of the queue in the slot
+    seq: usize,
+    queue: Option<Arc<MsgQueue>>,
+}
+
+impl MsgQueueSlot {
+    /// The queue in the slot, if it has `seq`
+    fn get(&self, seq: usize) -> Option<&Arc<MsgQueue>> {
+        self.queue.as_ref().filter(|_| self.seq == seq)
+    }
+}
+
+lazy_static! {
+    /// MSG_QUEUES: the message queues, indexed by the slots of their ids
+    static ref MSG_QUEUES: UPSafeCell<Vec<MsgQueueSlot>> =
+        unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+/// Get the id of the queue of `key`, creating it if `msgflg` has IPC_CREAT
+///
+/// IPC_PRIVATE always creates a new queue.
+pub fn msg_queue_get(key: usize, msgflg: usize) -> Result<usize, MsgError> {
+    let mut queues = MSG_QUEUES.exclusive_access();
+    if key != IPC_PRIVATE {
+        let found = queues
+            .iter()
+            .position(|slot| matches!(&slot.queue, Some(queue) if queue.key == key));
+        match found {
+            Some(_) if msgflg & IPC_CREAT != 0 && msgflg & IPC_EXCL != 0 => {
+                return Err(MsgError::Exists)
+            }
+            Some(index) => return Ok(queues[index].seq * MSGMNI + index),
+            None if msgflg & IPC_CREAT == 0 => return Err(MsgError::NotFound),
+            None => {}
+        }
+    }
+    let queue = Some(Arc::new(MsgQueue::new(key)));
+    if let Some(index) = queues.iter().position(|slot| slot.queue.is_none()) {
+        queues[index].queue = queue;
+        return Ok(queues[index].seq * MSGMNI + index);
+    }
+    if queues.len() == MSGMNI {
+        return Err(MsgError::NoSpace);
+    }
+    queues.push(MsgQueueSlot { seq: 0, queue });
+    Ok(queues.len() - 1)
+}
+
+/// Get the queue of `id`
+pub fn msg_queue(id: usize) -> Option<Arc<MsgQueue>> {
+    MSG_QUEUES
+        .exclusive_access()
+        .get(id % MSGMNI)
+        .and_then(|slot| slot.get(id / MSGMNI))
+        .cloned()
+}
+
+/// Remove the queue of `id`, return false if there is none
+pub fn msg_queue_remove(id: usize) -> bool {
+    let mut queues = MSG_QUEUES.exclusive_access();
+    let queue = match queues.get_mut(id % MSGMNI) {
+        Some(slot) if slot.get(id / MSGMNI).is_some() => {
+            // the next queue in the slot gets another id
+            slot.seq = (slot.seq + 1) % MSG_SEQ_MAX;
+            slot.queue.take()
+        }
+        _ => None,
+    };
+    drop(queues);
+    match queue {
+        Some(queue) => {
+            queue.remove();
+            true
+        }
+        None => false,
+    }
+}
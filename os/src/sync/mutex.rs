@@ -2,28 +2,64 @@
 
 use super::UPSafeCell;
 use crate::task::TaskControlBlock;
-use crate::task::{block_current_and_run_next, suspend_current_and_run_next};
-use crate::task::{current_task, wakeup_task};
-use alloc::{collections::VecDeque, sync::Arc};
+use crate::task::{block_current_and_run_next, block_current_interruptible};
+use crate::task::{current_interrupted, current_task, suspend_current_and_run_next, wakeup_task};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use core::cell::RefMut;
+use core::cmp::Reverse;
 
 /// Mutex trait
+///
+/// A mutex is robust: if its owner exits holding it, the mutex is released
+/// and the next owner is told by `take_owner_dead`.
 pub trait Mutex: Sync + Send {
     /// Lock the mutex
     fn lock(&self);
+    /// Lock the mutex, giving up at `expire_ms` if given, or when a signal
+    /// interrupts the wait
+    ///
+    /// Return false if the mutex is not locked.
+    fn lock_timeout(&self, expire_ms: Option<usize>) -> bool;
     /// Unlock the mutex
     fn unlock(&self);
+    /// Is the mutex held by `task`?
+    fn is_owner(&self, task: &Arc<TaskControlBlock>) -> bool;
+    /// Is the mutex free with nobody waiting?
+    fn is_idle(&self) -> bool;
+    /// Release the mutex whose owner exited holding it
+    fn release_dead_owner(&self);
+    /// Was the mutex released by an exited owner before the current owner
+    /// locked it? The flag is cleared.
+    fn take_owner_dead(&self) -> bool;
 }
 
 /// Spinlock Mutex struct
 pub struct MutexSpin {
-    locked: UPSafeCell<bool>,
+    inner: UPSafeCell<MutexSpinInner>,
+}
+
+pub struct MutexSpinInner {
+    /// the task holding the mutex
+    owner: Option<Arc<TaskControlBlock>>,
+    /// the last owner exited holding the mutex
+    owner_dead: bool,
+    /// the number of tasks spinning on the mutex
+    waiters: usize,
 }
 
 impl MutexSpin {
     /// Create a new spinlock mutex
     pub fn new() -> Self {
         Self {
-            locked: unsafe { UPSafeCell::new(false) },
+            inner: unsafe {
+                UPSafeCell::new(MutexSpinInner {
+                    owner: None,
+                    owner_dead: false,
+                    waiters: 0,
+                })
+            },
         }
     }
 }
@@ -32,47 +68,225 @@ impl Mutex for MutexSpin {
     /// Lock the spinlock mutex
     fn lock(&self) {
         trace!("kernel: MutexSpin::lock");
+        self.inner.exclusive_access().waiters += 1;
         loop {
-            let mut locked = self.locked.exclusive_access();
-            if *locked {
-                drop(locked);
+            let mut inner = self.inner.exclusive_access();
+            if inner.owner.is_some() {
+                drop(inner);
                 suspend_current_and_run_next();
                 continue;
             } else {
-                *locked = true;
+                inner.owner = Some(current_task().unwrap());
+                inner.waiters -= 1;
                 return;
             }
         }
     }
 
+    fn lock_timeout(&self, expire_ms: Option<usize>) -> bool {
+        trace!("kernel: MutexSpin::lock_timeout");
+        self.inner.exclusive_access().waiters += 1;
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.owner.is_none() {
+                inner.owner = Some(current_task().unwrap());
+                inner.waiters -= 1;
+                return true;
+            }
+            if expire_ms.is_some_and(|expire_ms| get_time_ms() >= expire_ms)
+                || current_interrupted()
+            {
+                inner.waiters -= 1;
+                return false;
+            }
+            drop(inner);
+            suspend_current_and_run_next();
+        }
+    }
+
     fn unlock(&self) {
         trace!("kernel: MutexSpin::unlock");
-        let mut locked = self.locked.exclusive_access();
-        *locked = false;
+        self.inner.exclusive_access().owner = None;
+    }
+
+    fn is_owner(&self, task: &Arc<TaskControlBlock>) -> bool {
+        matches!(&self.inner.exclusive_access().owner, Some(owner) if Arc::ptr_eq(owner, task))
+    }
+
+    fn is_idle(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        inner.owner.is_none() && inner.waiters == 0
+    }
+
+    fn release_dead_owner(&self) {
+        trace!("kernel: MutexSpin::release_dead_owner");
+        let mut inner = self.inner.exclusive_access();
+        inner.owner = None;
+        inner.owner_dead = true;
+    }
+
+    fn take_owner_dead(&self) -> bool {
+        core::mem::take(&mut self.inner.exclusive_access().owner_dead)
     }
 }
 
 /// Blocking Mutex struct
+///
+/// The owner inherits the priority of the highest waiter, along the chain
+/// of blocking mutexes the owners wait on in turn, until it unlocks.
 pub struct MutexBlocking {
-    inner: UPSafeCell<MutexBlockingInner>,
+    inner: Arc<UPSafeCell<MutexBlockingInner>>,
 }
 
+/// The state of a blocking mutex, shared with the tasks waiting on or
+/// holding it for the priority chain walk
 pub struct MutexBlockingInner {
     locked: bool,
+    /// the task holding the mutex
+    owner: Option<Arc<TaskControlBlock>>,
+    /// the last owner exited holding the mutex
+    owner_dead: bool,
     wait_queue: VecDeque<Arc<TaskControlBlock>>,
 }
 
+/// Raise the owner of `mutex` to `priority`, and on along the chain of the
+/// mutexes the owners wait on
+fn propagate_priority(mutex: &Arc<UPSafeCell<MutexBlockingInner>>, priority: usize) {
+    let mut mutex = Arc::clone(mutex);
+    loop {
+        let owner = match &mutex.exclusive_access().owner {
+            Some(owner) => Arc::clone(owner),
+            None => return,
+        };
+        let mut owner_inner = owner.inner_exclusive_access();
+        // stopping here also ends the walk around a deadlock cycle
+        if owner_inner.effective_priority >= priority {
+            return;
+        }
+        owner_inner.effective_priority = priority;
+        mutex = match owner_inner.blocked_on.as_ref().and_then(Weak::upgrade) {
+            Some(next) => next,
+            None => return,
+        };
+    }
+}
+
+/// Recompute the priority of `task` from its own priority and the waiters
+/// of the blocking mutexes it holds, return whether it has changed
+pub fn update_priority(task: &Arc<TaskControlBlock>) -> bool {
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner
+        .held_mutexes
+        .retain(|mutex| mutex.strong_count() > 0);
+    let mut priority = task_inner.priority;
+    let held_mutexes = task_inner.held_mutexes.clone();
+    drop(task_inner);
+    for mutex in held_mutexes.iter().filter_map(Weak::upgrade) {
+        for waiter in mutex.exclusive_access().wait_queue.iter() {
+            if !Arc::ptr_eq(waiter, task) {
+                priority = priority.max(waiter.inner_exclusive_access().effective_priority);
+            }
+        }
+    }
+    let mut task_inner = task.inner_exclusive_access();
+    let changed = task_inner.effective_priority != priority;
+    task_inner.effective_priority = priority;
+    changed
+}
+
+/// Recompute the priority of the owner of `mutex` after a waiter leaves it,
+/// and on along the chain of the mutexes the owners wait on
+fn restore_priority(mutex: &Arc<UPSafeCell<MutexBlockingInner>>) {
+    let mut mutex = Arc::clone(mutex);
+    loop {
+        let owner = match &mutex.exclusive_access().owner {
+            Some(owner) => Arc::clone(owner),
+            None => return,
+        };
+        // an unchanged owner leaves the rest of the chain as it is, which
+        // also ends the walk around a deadlock cycle
+        if !update_priority(&owner) {
+            return;
+        }
+        let owner_inner = owner.inner_exclusive_access();
+        mutex = match owner_inner.blocked_on.as_ref().and_then(Weak::upgrade) {
+            Some(next) => next,
+            None => return,
+        };
+    }
+}
+
 impl MutexBlocking {
     /// Create a new blocking mutex
     pub fn new() -> Self {
         trace!("kernel: MutexBlocking::new");
         Self {
-            inner: unsafe {
+            inner: Arc::new(unsafe {
                 UPSafeCell::new(MutexBlockingInner {
                     locked: false,
+                    owner: None,
+                    owner_dead: false,
                     wait_queue: VecDeque::new(),
                 })
-            },
+            }),
+        }
+    }
+
+    /// Take the free mutex for `task`
+    fn acquire(&self, mutex_inner: &mut MutexBlockingInner, task: &Arc<TaskControlBlock>) {
+        mutex_inner.locked = true;
+        mutex_inner.owner = Some(Arc::clone(task));
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.blocked_on = None;
+        task_inner.held_mutexes.push(Arc::downgrade(&self.inner));
+    }
+
+    /// Queue `task` on the mutex and lend its priority to the owner
+    fn enqueue(
+        &self,
+        mut mutex_inner: RefMut<'_, MutexBlockingInner>,
+        task: &Arc<TaskControlBlock>,
+    ) {
+        mutex_inner.wait_queue.push_back(Arc::clone(task));
+        drop(mutex_inner);
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.blocked_on = Some(Arc::downgrade(&self.inner));
+        let priority = task_inner.effective_priority;
+        drop(task_inner);
+        propagate_priority(&self.inner, priority);
+    }
+
+    /// Release the mutex and hand it over to a waiter if any
+    fn release(&self, owner_dead: bool) {
+        let mut mutex_inner = self.inner.exclusive_access();
+        assert!(mutex_inner.locked);
+        mutex_inner.owner_dead = owner_dead;
+        let owner = mutex_inner.owner.take();
+        // hand the mutex over to the first waiter of the highest priority
+        let next = mutex_inner
+            .wait_queue
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, t)| Reverse(t.inner_exclusive_access().effective_priority))
+            .map(|(pos, _)| pos);
+        let waking_task = next.map(|pos| mutex_inner.wait_queue.remove(pos).unwrap());
+        match &waking_task {
+            Some(waking_task) => self.acquire(&mut mutex_inner, waking_task),
+            None => mutex_inner.locked = false,
+        }
+        drop(mutex_inner);
+        // undo the boost of the old owner
+        if let Some(owner) = owner {
+            owner
+                .inner_exclusive_access()
+                .held_mutexes
+                .retain(|mutex| mutex.as_ptr() != Arc::as_ptr(&self.inner));
+            update_priority(&owner);
+        }
+        // the remaining waiters lend their priority to the new owner
+        if let Some(waking_task) = waking_task {
+            update_priority(&waking_task);
+            wakeup_task(waking_task);
         }
     }
 }
@@ -81,25 +295,73 @@ impl Mutex for MutexBlocking {
     /// lock the blocking mutex
     fn lock(&self) {
         trace!("kernel: MutexBlocking::lock");
+        let task = current_task().unwrap();
         let mut mutex_inner = self.inner.exclusive_access();
         if mutex_inner.locked {
-            mutex_inner.wait_queue.push_back(current_task().unwrap());
-            drop(mutex_inner);
+            self.enqueue(mutex_inner, &task);
+            // the mutex is handed over by the unlocking task
             block_current_and_run_next();
         } else {
-            mutex_inner.locked = true;
+            self.acquire(&mut mutex_inner, &task);
+        }
+    }
+
+    /// lock the blocking mutex, giving up at `expire_ms` or on a signal
+    fn lock_timeout(&self, expire_ms: Option<usize>) -> bool {
+        trace!("kernel: MutexBlocking::lock_timeout");
+        let task = current_task().unwrap();
+        let mut mutex_inner = self.inner.exclusive_access();
+        if !mutex_inner.locked {
+            self.acquire(&mut mutex_inner, &task);
+            return true;
+        }
+        self.enqueue(mutex_inner, &task);
+        if let Some(expire_ms) = expire_ms {
+            add_timer(expire_ms, Arc::clone(&task));
         }
+        block_current_interruptible();
+        if expire_ms.is_some() {
+            remove_timer(Arc::clone(&task));
+        }
+        // still queued means it is the timer or a signal who woke us up,
+        // otherwise the mutex has been handed over to us
+        let mut mutex_inner = self.inner.exclusive_access();
+        if let Some(pos) = mutex_inner
+            .wait_queue
+            .iter()
+            .position(|t| Arc::ptr_eq(t, &task))
+        {
+            mutex_inner.wait_queue.remove(pos);
+            drop(mutex_inner);
+            task.inner_exclusive_access().blocked_on = None;
+            // the owners along the chain no longer inherit our priority
+            restore_priority(&self.inner);
+            return false;
+        }
+        true
     }
 
     /// unlock the blocking mutex
     fn unlock(&self) {
         trace!("kernel: MutexBlocking::unlock");
-        let mut mutex_inner = self.inner.exclusive_access();
-        assert!(mutex_inner.locked);
-        if let Some(waking_task) = mutex_inner.wait_queue.pop_front() {
-            wakeup_task(waking_task);
-        } else {
-            mutex_inner.locked = false;
-        }
+        self.release(false);
+    }
+
+    fn is_owner(&self, task: &Arc<TaskControlBlock>) -> bool {
+        matches!(&self.inner.exclusive_access().owner, Some(owner) if Arc::ptr_eq(owner, task))
+    }
+
+    fn is_idle(&self) -> bool {
+        let mutex_inner = self.inner.exclusive_access();
+        !mutex_inner.locked && mutex_inner.wait_queue.is_empty()
+    }
+
+    fn release_dead_owner(&self) {
+        trace!("kernel: MutexBlocking::release_dead_owner");
+        self.release(true);
+    }
+
+    fn take_owner_dead(&self) -> bool {
+        core::mem::take(&mut self.inner.exclusive_access().owner_dead)
     }
 }
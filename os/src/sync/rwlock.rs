@@ -0,0 +1,121 @@
+//! Reader-writer lock (writer-preferring)
+
+use super::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use alloc::{collections::VecDeque, sync::Arc};
+
+/// The most readers holding a rwlock at the same time, a writer takes all
+/// these units at once in the deadlock detection
+pub const RWLOCK_MAX_READERS: usize = 64;
+
+/// Reader-writer lock structure
+///
+/// New readers wait as long as a writer is waiting, so writers are not
+/// starved by a stream of readers.
+pub struct RwLock {
+    inner: UPSafeCell<RwLockInner>,
+}
+
+pub struct RwLockInner {
+    /// the number of readers holding the lock
+    readers: usize,
+    /// is the lock held by a writer?
+    writer: bool,
+    read_queue: VecDeque<Arc<TaskControlBlock>>,
+    write_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Default for RwLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RwLock {
+    /// Create a new reader-writer lock
+    pub fn new() -> Self {
+        trace!("kernel: RwLock::new");
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(RwLockInner {
+                    readers: 0,
+                    writer: false,
+                    read_queue: VecDeque::new(),
+                    write_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// lock for reading
+    pub fn read_lock(&self) {
+        trace!("kernel: RwLock::read_lock");
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer || !inner.write_queue.is_empty() || inner.readers == RWLOCK_MAX_READERS {
+            inner.read_queue.push_back(current_task().unwrap());
+            drop(inner);
+            // the lock is handed over by the unlocking task
+            block_current_and_run_next();
+        } else {
+            inner.readers += 1;
+        }
+    }
+
+    /// lock for writing
+    pub fn write_lock(&self) {
+        trace!("kernel: RwLock::write_lock");
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer || inner.readers > 0 {
+            inner.write_queue.push_back(current_task().unwrap());
+            drop(inner);
+            // the lock is handed over by the unlocking task
+            block_current_and_run_next();
+        } else {
+            inner.writer = true;
+        }
+    }
+
+    /// Is the lock held by a writer?
+    pub fn is_write_locked(&self) -> bool {
+        self.inner.exclusive_access().writer
+    }
+
+    /// Is the lock free with nobody waiting?
+    pub fn is_idle(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        !inner.writer
+            && inner.readers == 0
+            && inner.read_queue.is_empty()
+            && inner.write_queue.is_empty()
+    }
+
+    /// unlock, held either by a writer or by the current reader
+    pub fn unlock(&self) {
+        trace!("kernel: RwLock::unlock");
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer {
+            inner.writer = false;
+        } else {
+            assert!(inner.readers > 0);
+            inner.readers -= 1;
+        }
+        // a waiting writer goes first, otherwise let in the waiting readers
+        if !inner.write_queue.is_empty() {
+            if inner.readers == 0 {
+                inner.writer = true;
+                let task = inner.write_queue.pop_front().unwrap();
+                wakeup_task(task);
+            }
+            return;
+        }
+        while inner.readers < RWLOCK_MAX_READERS {
+            match inner.read_queue.pop_front() {
+                Some(task) => {
+                    inner.readers += 1;
+                    wakeup_task(task);
+                }
+                None => break,
+            }
+        }
+    }
+}
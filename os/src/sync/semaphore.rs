@@ -1,7 +1,8 @@
 //! Semaphore
 
 use crate::sync::UPSafeCell;
-use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use crate::task::{block_current_interruptible, current_task, wakeup_task, TaskControlBlock};
+use crate::timer::{add_timer, remove_timer};
 use alloc::{collections::VecDeque, sync::Arc};
 
 /// semaphore structure
@@ -41,15 +42,39 @@ impl Semaphore {
         }
     }
 
-    /// down operation of semaphore
-    pub fn down(&self) {
+    /// Is nobody waiting on the semaphore?
+    pub fn is_idle(&self) -> bool {
+        self.inner.exclusive_access().wait_queue.is_empty()
+    }
+
+    /// down operation of semaphore, giving up at `expire_ms` if given, or
+    /// when a signal interrupts the wait
+    ///
+    /// Return false if no unit is acquired.
+    pub fn down(&self, expire_ms: Option<usize>) -> bool {
         trace!("kernel: Semaphore::down");
         let mut inner = self.inner.exclusive_access();
         inner.count -= 1;
-        if inner.count < 0 {
-            inner.wait_queue.push_back(current_task().unwrap());
-            drop(inner);
-            block_current_and_run_next();
+        if inner.count >= 0 {
+            return true;
+        }
+        let task = current_task().unwrap();
+        inner.wait_queue.push_back(Arc::clone(&task));
+        drop(inner);
+        if let Some(expire_ms) = expire_ms {
+            add_timer(expire_ms, Arc::clone(&task));
+        }
+        block_current_interruptible();
+        if expire_ms.is_some() {
+            remove_timer(Arc::clone(&task));
+        }
+        // still queued means it is the timer or a signal who woke us up
+        let mut inner = self.inner.exclusive_access();
+        if let Some(pos) = inner.wait_queue.iter().position(|t| Arc::ptr_eq(t, &task)) {
+            inner.wait_queue.remove(pos);
+            inner.count += 1;
+            return false;
         }
+        true
     }
 }
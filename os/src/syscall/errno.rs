@@ -0,0 +1,69 @@
+//! Error numbers returned by syscalls
+//!
+//! A syscall failing with one of these returns its negation, e.g. `-EAGAIN`.
+//! Older syscalls keep returning -1 on any error.
+
+/// Operation not permitted
+pub const EPERM: isize = 1;
+/// No such file or directory
+pub const ENOENT: isize = 2;
+/// No such process
+pub const ESRCH: isize = 3;
+/// Interrupted system call
+pub const EINTR: isize = 4;
+/// Argument list too long
+pub const E2BIG: isize = 7;
+/// Bad file descriptor
+pub const EBADF: isize = 9;
+/// Try again
+pub const EAGAIN: isize = 11;
+/// Bad address
+pub const EFAULT: isize = 14;
+/// Device or resource busy
+pub const EBUSY: isize = 16;
+/// File exists
+pub const EEXIST: isize = 17;
+/// Not a directory
+pub const ENOTDIR: isize = 20;
+/// Is a directory
+pub const EISDIR: isize = 21;
+/// Invalid argument
+pub const EINVAL: isize = 22;
+/// Too many open files
+pub const EMFILE: isize = 24;
+/// No space left on device
+pub const ENOSPC: isize = 28;
+/// Math result not representable
+pub const ERANGE: isize = 34;
+/// File name too long
+pub const ENAMETOOLONG: isize = 36;
+/// Function not implemented
+pub const ENOSYS: isize = 38;
+/// Directory not empty
+pub const ENOTEMPTY: isize = 39;
+/// No message of desired type
+pub const ENOMSG: isize = 42;
+/// Identifier removed
+pub const EIDRM: isize = 43;
+/// Socket operation on non-socket
+pub const ENOTSOCK: isize = 88;
+/// Message too long
+pub const EMSGSIZE: isize = 90;
+/// Protocol not supported
+pub const EPROTONOSUPPORT: isize = 93;
+/// Operation not supported on transport endpoint
+pub const EOPNOTSUPP: isize = 95;
+/// Address family not supported by protocol
+pub const EAFNOSUPPORT: isize = 97;
+/// Address already in use
+pub const EADDRINUSE: isize = 98;
+/// Transport endpoint is already connected
+pub const EISCONN: isize = 106;
+/// Transport endpoint is not connected
+pub const ENOTCONN: isize = 107;
+/// Connection timed out
+pub const ETIMEDOUT: isize = 110;
+/// Connection refused
+pub const ECONNREFUSED: isize = 111;
+/// Owner died
+pub const EOWNERDEAD: isize = 130;
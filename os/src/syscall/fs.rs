@@ -1,7 +1,44 @@
-use crate::fs::{make_pipe, open_file, OpenFlags, Stat};
-use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
-use crate::task::{current_process, current_task, current_user_token};
+use super::errno::{
+    EAGAIN, EBADF, EBUSY, EEXIST, EFAULT, EINTR, EINVAL, EISDIR, EMFILE, ENAMETOOLONG, ENOENT,
+    ENOTDIR, ENOTEMPTY, EPERM, ERANGE,
+};
+use super::sync::{read_timeout, TimeSpec};
+use crate::fs::{
+    check_dir, join_path, link, make_dir, make_fifo, make_pipe, normalize_path, open, remove_dir,
+    unlink, EventFd, File, FsError, OpenFlags, SignalFd, Stat, StatMode, PIPE_MAX_SIZE,
+};
+use crate::mm::{
+    copy_from_user, copy_to_user, translated_byte_buffer, translated_refmut, translated_str,
+    UserBuffer,
+};
+use crate::task::{
+    block_current_interruptible, current_process, current_task, current_user_token, SignalFlags,
+};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+pub(super) fn fs_errno(err: FsError) -> isize {
+    match err {
+        FsError::NotFound => -ENOENT,
+        FsError::NotDir => -ENOTDIR,
+        FsError::Exists => -EEXIST,
+        FsError::NotEmpty => -ENOTEMPTY,
+        FsError::NameTooLong => -ENAMETOOLONG,
+        FsError::Invalid => -EINVAL,
+        FsError::Busy => -EBUSY,
+        FsError::IsDir => -EISDIR,
+        FsError::NotPermitted => -EPERM,
+        FsError::Interrupted => -EINTR,
+    }
+}
+
+/// The absolute path of `path`, a relative path is from the current directory
+pub(super) fn absolute_path(path: &str) -> String {
+    join_path(&current_process().inner_exclusive_access().cwd, path)
+}
+
 /// write syscall
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     trace!(
@@ -21,7 +58,13 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
         let file = file.clone();
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        if file.nonblocking() && !file.write_ready() {
+            return -EAGAIN;
+        }
+        match file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) {
+            Ok(len) => len as isize,
+            Err(err) => fs_errno(err),
+        }
     } else {
         -1
     }
@@ -45,8 +88,14 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
         }
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
+        if file.nonblocking() && !file.read_ready() {
+            return -EAGAIN;
+        }
         trace!("kernel: sys_read .. file.read");
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        match file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) {
+            Ok(len) => len as isize,
+            Err(err) => fs_errno(err),
+        }
     } else {
         -1
     }
@@ -59,12 +108,15 @@ pub fn sys_open(path: *const u8, flags: u32) -> isize {
     );
     let process = current_process();
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
+    let path = absolute_path(&translated_str(token, path));
+    if let Some(inode) = open(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
         let mut inner = process.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
+        if let Some(fd) = inner.alloc_fd() {
+            inner.fd_table[fd] = Some(inode);
+            fd as isize
+        } else {
+            -1
+        }
     } else {
         -1
     }
@@ -86,8 +138,8 @@ pub fn sys_close(fd: usize) -> isize {
     inner.fd_table[fd].take();
     0
 }
-/// pipe syscall
-pub fn sys_pipe(pipe: *mut usize) -> isize {
+/// pipe syscall, `flags` may have O_NONBLOCK
+pub fn sys_pipe(pipe: *mut usize, flags: u32) -> isize {
     trace!(
         "kernel:pid[{}] sys_pipe",
         current_task().unwrap().process.upgrade().unwrap().getpid()
@@ -96,14 +148,164 @@ pub fn sys_pipe(pipe: *mut usize) -> isize {
     let token = current_user_token();
     let mut inner = process.inner_exclusive_access();
     let (pipe_read, pipe_write) = make_pipe();
-    let read_fd = inner.alloc_fd();
+    if OpenFlags::from_bits_truncate(flags).contains(OpenFlags::NONBLOCK) {
+        pipe_read.set_nonblocking(true);
+        pipe_write.set_nonblocking(true);
+    }
+    let read_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
     inner.fd_table[read_fd] = Some(pipe_read);
-    let write_fd = inner.alloc_fd();
+    let write_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => {
+            inner.fd_table[read_fd] = None;
+            return -1;
+        }
+    };
     inner.fd_table[write_fd] = Some(pipe_write);
     *translated_refmut(token, pipe) = read_fd;
     *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
     0
 }
+/// Get the status flags of a file descriptor
+const F_GETFL: usize = 3;
+/// Set the status flags of a file descriptor, only O_NONBLOCK can be changed
+const F_SETFL: usize = 4;
+/// Set the capacity of a pipe
+const F_SETPIPE_SZ: usize = 1031;
+/// Get the capacity of a pipe
+const F_GETPIPE_SZ: usize = 1032;
+
+/// fcntl syscall
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_fcntl",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => Arc::clone(file),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    match cmd {
+        F_GETFL => {
+            let mut flags = match (file.readable(), file.writable()) {
+                (true, true) => OpenFlags::RDWR,
+                (false, true) => OpenFlags::WRONLY,
+                _ => OpenFlags::RDONLY,
+            };
+            if file.nonblocking() {
+                flags |= OpenFlags::NONBLOCK;
+            }
+            flags.bits() as isize
+        }
+        F_SETFL => {
+            let flags = OpenFlags::from_bits_truncate(arg as u32);
+            file.set_nonblocking(flags.contains(OpenFlags::NONBLOCK));
+            0
+        }
+        F_GETPIPE_SZ => match file.pipe_capacity() {
+            Some(capacity) => capacity as isize,
+            None => -EBADF,
+        },
+        F_SETPIPE_SZ => {
+            if file.pipe_capacity().is_none() {
+                return -EBADF;
+            }
+            if arg == 0 {
+                return -EINVAL;
+            }
+            if arg > PIPE_MAX_SIZE {
+                return -EPERM;
+            }
+            // the data in the pipe has to fit
+            if !file.set_pipe_capacity(arg) {
+                return -EBUSY;
+            }
+            arg as isize
+        }
+        _ => -EINVAL,
+    }
+}
+
+/// The file type bits of a mode
+const S_IFMT: u32 = 0o170000;
+
+/// mknodat syscall, only FIFOs can be made
+///
+/// `dirfd` is ignored, a relative path is from the current directory.
+/// Return -EEXIST if the path exists, or -EINVAL if `mode` is not a FIFO.
+pub fn sys_mknodat(_dirfd: isize, path: *const u8, mode: u32, _dev: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_mknodat",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if mode & S_IFMT != StatMode::FIFO.bits() {
+        return -EINVAL;
+    }
+    let path = absolute_path(&translated_str(current_user_token(), path));
+    match make_fifo(path.as_str()) {
+        Ok(()) => 0,
+        Err(err) => fs_errno(err),
+    }
+}
+
+/// mkdirat syscall
+///
+/// `dirfd` is ignored, a relative path is from the current directory.
+/// `mode` is ignored as files have no permissions.
+pub fn sys_mkdirat(_dirfd: isize, path: *const u8, _mode: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_mkdirat",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let path = absolute_path(&translated_str(current_user_token(), path));
+    match make_dir(path.as_str()) {
+        Ok(()) => 0,
+        Err(err) => fs_errno(err),
+    }
+}
+
+/// chdir syscall
+pub fn sys_chdir(path: *const u8) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_chdir",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let path = absolute_path(&translated_str(current_user_token(), path));
+    // ".." is found on the disk, only the path kept is normalized
+    if let Err(err) = check_dir(path.as_str()) {
+        return fs_errno(err);
+    }
+    current_process().inner_exclusive_access().cwd = normalize_path(path.as_str());
+    0
+}
+
+/// getcwd syscall
+///
+/// Write the current directory ended by a NUL to `buf` of `size` bytes.
+/// Return the length written with the NUL, or -ERANGE if it does not fit.
+pub fn sys_getcwd(buf: *mut u8, size: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_getcwd",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if buf.is_null() {
+        return -EFAULT;
+    }
+    let mut cwd = current_process().inner_exclusive_access().cwd.clone();
+    cwd.push('\0');
+    if cwd.len() > size {
+        return -ERANGE;
+    }
+    UserBuffer::new(translated_byte_buffer(current_user_token(), buf, cwd.len()))
+        .write_bytes(cwd.as_bytes());
+    cwd.len() as isize
+}
 /// dup syscall
 pub fn sys_dup(fd: usize) -> isize {
     trace!(
@@ -118,34 +320,290 @@ pub fn sys_dup(fd: usize) -> isize {
     if inner.fd_table[fd].is_none() {
         return -1;
     }
-    let new_fd = inner.alloc_fd();
-    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
-    new_fd as isize
+    if let Some(new_fd) = inner.alloc_fd() {
+        inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
+        new_fd as isize
+    } else {
+        -1
+    }
+}
+
+/// A read takes 1 from the eventfd counter instead of all of it
+const EFD_SEMAPHORE: u32 = 1;
+
+/// eventfd2 syscall, `flags` may have EFD_SEMAPHORE and O_NONBLOCK
+pub fn sys_eventfd2(initval: u32, flags: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_eventfd2",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if flags & !(EFD_SEMAPHORE | OpenFlags::NONBLOCK.bits()) != 0 {
+        return -EINVAL;
+    }
+    let eventfd = Arc::new(EventFd::new(initval as u64, flags & EFD_SEMAPHORE != 0));
+    eventfd.set_nonblocking(flags & OpenFlags::NONBLOCK.bits() != 0);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.alloc_fd() {
+        Some(fd) => {
+            inner.fd_table[fd] = Some(eventfd);
+            fd as isize
+        }
+        None => -EMFILE,
+    }
+}
+
+/// signalfd4 syscall, only a new signalfd can be created
+///
+/// `fd` has to be -1, and `mask` points to a signal mask like that of
+/// sigprocmask, in the first 4 of `sizemask` bytes. `flags` may have
+/// O_NONBLOCK.
+pub fn sys_signalfd4(fd: isize, mask: *const u32, sizemask: usize, flags: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_signalfd4",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if fd != -1 || sizemask < core::mem::size_of::<u32>() {
+        return -EINVAL;
+    }
+    if flags & !OpenFlags::NONBLOCK.bits() != 0 {
+        return -EINVAL;
+    }
+    if mask.is_null() {
+        return -EFAULT;
+    }
+    let mask = SignalFlags::from_bits_truncate(copy_from_user(current_user_token(), mask));
+    let signalfd = Arc::new(SignalFd::new(mask));
+    signalfd.set_nonblocking(flags & OpenFlags::NONBLOCK.bits() != 0);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.alloc_fd() {
+        Some(fd) => {
+            inner.fd_table[fd] = Some(signalfd);
+            fd as isize
+        }
+        None => -EMFILE,
+    }
+}
+
+/// A file descriptor to poll
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PollFd {
+    /// the file descriptor, ignored if negative
+    pub fd: i32,
+    /// the events to wait for
+    pub events: i16,
+    /// the events happened
+    pub revents: i16,
+}
+
+/// There is data to read
+const POLLIN: i16 = 0x1;
+/// Writing now will not block
+const POLLOUT: i16 = 0x4;
+/// The other end is closed
+const POLLHUP: i16 = 0x10;
+/// The file descriptor is not open
+const POLLNVAL: i16 = 0x20;
+/// How often files which can not wake up a waiter are checked again
+const POLL_INTERVAL_MS: usize = 10;
+
+/// Wait until any of the `nfds` file descriptors at `fds` is ready, or until
+/// `expire_ms` if given, return -EINTR if a signal interrupts the wait
+fn do_poll(fds: *mut PollFd, nfds: usize, expire_ms: Option<usize>) -> isize {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let mut pollfds: Vec<PollFd> = (0..nfds)
+        .map(|i| copy_from_user(token, unsafe { fds.add(i) }))
+        .collect();
+    loop {
+        let process = current_process();
+        let inner = process.inner_exclusive_access();
+        let files: Vec<Option<Arc<dyn File + Send + Sync>>> = pollfds
+            .iter()
+            .map(|pollfd| {
+                if pollfd.fd < 0 {
+                    return None;
+                }
+                inner.fd_table.get(pollfd.fd as usize).cloned().flatten()
+            })
+            .collect();
+        drop(inner);
+        drop(process);
+        let mut ready = 0;
+        for (pollfd, file) in pollfds.iter_mut().zip(files.iter()) {
+            pollfd.revents = 0;
+            match file {
+                Some(file) => {
+                    if pollfd.events & POLLIN != 0 && file.readable() && file.read_ready() {
+                        pollfd.revents |= POLLIN;
+                    }
+                    if pollfd.events & POLLOUT != 0 && file.writable() && file.write_ready() {
+                        pollfd.revents |= POLLOUT;
+                    }
+                    if file.hung_up() {
+                        pollfd.revents |= POLLHUP;
+                    }
+                }
+                None if pollfd.fd >= 0 => pollfd.revents = POLLNVAL,
+                None => {}
+            }
+            if pollfd.revents != 0 {
+                ready += 1;
+            }
+        }
+        let now_ms = get_time_ms();
+        if ready > 0 || matches!(expire_ms, Some(expire_ms) if now_ms >= expire_ms) {
+            for (i, pollfd) in pollfds.iter().enumerate() {
+                copy_to_user(token, unsafe { fds.add(i) }, pollfd);
+            }
+            return ready;
+        }
+        // sleep until a file wakes us up, or it is time to check again
+        let mut polled = false;
+        for file in files.iter().flatten() {
+            polled |= !file.register_waiter(&task);
+        }
+        let wake_ms = match (polled, expire_ms) {
+            (true, Some(expire_ms)) => Some(expire_ms.min(now_ms + POLL_INTERVAL_MS)),
+            (true, None) => Some(now_ms + POLL_INTERVAL_MS),
+            (false, expire_ms) => expire_ms,
+        };
+        if let Some(wake_ms) = wake_ms {
+            add_timer(wake_ms, Arc::clone(&task));
+        }
+        let woken = block_current_interruptible();
+        for file in files.iter().flatten() {
+            file.unregister_waiter(&task);
+        }
+        if wake_ms.is_some() {
+            remove_timer(Arc::clone(&task));
+        }
+        if !woken {
+            return -EINTR;
+        }
+    }
+}
+
+/// poll syscall
+///
+/// Wait for at most `timeout_ms` milliseconds, forever if it is negative.
+/// Return the number of file descriptors with events.
+pub fn sys_poll(fds: *mut PollFd, nfds: usize, timeout_ms: isize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_poll",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let expire_ms = if timeout_ms < 0 {
+        None
+    } else {
+        Some(get_time_ms() + timeout_ms as usize)
+    };
+    do_poll(fds, nfds, expire_ms)
+}
+
+/// ppoll syscall
+///
+/// Wait until `*timeout` passes, forever if it is null. The signal mask is
+/// replaced by `*sigmask` while waiting unless it is null. Return -EINTR if
+/// a signal it does not block interrupts the wait, the signal is handled
+/// under `*sigmask` before the old mask is restored.
+pub fn sys_ppoll(
+    fds: *mut PollFd,
+    nfds: usize,
+    timeout: *const TimeSpec,
+    sigmask: *const u32,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_ppoll",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let token = current_user_token();
+    let expire_ms = if timeout.is_null() {
+        None
+    } else {
+        match read_timeout(token, timeout) {
+            Ok(expire_ms) => Some(expire_ms),
+            Err(errno) => return errno,
+        }
+    };
+    if sigmask.is_null() {
+        return do_poll(fds, nfds, expire_ms);
+    }
+    let mask =
+        SignalFlags::from_bits_truncate(copy_from_user(token, sigmask)) - SignalFlags::UNCATCHABLE;
+    let task = current_task().unwrap();
+    let old_mask = core::mem::replace(&mut task.inner_exclusive_access().signal_mask, mask);
+    let ret = do_poll(fds, nfds, expire_ms);
+    let mut task_inner = task.inner_exclusive_access();
+    if ret == -EINTR {
+        // the signal is handled under `mask`, then `old_mask` is restored
+        task_inner.saved_signal_mask = Some(old_mask);
+    } else {
+        task_inner.signal_mask = old_mask;
+    }
+    ret
 }
 
-/// YOUR JOB: Implement fstat.
-pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
+/// fstat syscall
+pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
     trace!(
-        "kernel:pid[{}] sys_fstat NOT IMPLEMENTED",
+        "kernel:pid[{}] sys_fstat",
         current_task().unwrap().process.upgrade().unwrap().getpid()
     );
-    -1
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => Arc::clone(file),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    if st.is_null() {
+        return -EFAULT;
+    }
+    // the stat may cross a page boundary
+    copy_to_user(current_user_token(), st, &file.stat());
+    0
 }
 
-/// YOUR JOB: Implement linkat.
-pub fn sys_linkat(_old_name: *const u8, _new_name: *const u8) -> isize {
+/// linkat syscall
+///
+/// The dirfds and flags are ignored, a relative path is from the current
+/// directory. A directory can not be linked.
+pub fn sys_linkat(old_path: *const u8, new_path: *const u8) -> isize {
     trace!(
-        "kernel:pid[{}] sys_linkat NOT IMPLEMENTED",
+        "kernel:pid[{}] sys_linkat",
         current_task().unwrap().process.upgrade().unwrap().getpid()
     );
-    -1
+    let token = current_user_token();
+    let old_path = absolute_path(&translated_str(token, old_path));
+    let new_path = absolute_path(&translated_str(token, new_path));
+    match link(old_path.as_str(), new_path.as_str()) {
+        Ok(()) => 0,
+        Err(err) => fs_errno(err),
+    }
 }
 
-/// YOUR JOB: Implement unlinkat.
-pub fn sys_unlinkat(_name: *const u8) -> isize {
+/// Remove a directory instead of a file
+const AT_REMOVEDIR: u32 = 0x200;
+
+/// unlinkat syscall, a directory is removed with AT_REMOVEDIR
+///
+/// `dirfd` is ignored, a relative path is from the current directory.
+pub fn sys_unlinkat(_dirfd: isize, path: *const u8, flags: u32) -> isize {
     trace!(
-        "kernel:pid[{}] sys_unlinkat NOT IMPLEMENTED",
+        "kernel:pid[{}] sys_unlinkat",
         current_task().unwrap().process.upgrade().unwrap().getpid()
     );
-    -1
+    let path = absolute_path(&translated_str(current_user_token(), path));
+    let result = if flags & AT_REMOVEDIR != 0 {
+        remove_dir(path.as_str())
+    } else {
+        unlink(path.as_str())
+    };
+    match result {
+        Ok(()) => 0,
+        Err(err) => fs_errno(err),
+    }
 }
@@ -0,0 +1,179 @@
+//! Inter-process communication syscalls
+
+use super::errno::{E2BIG, EAGAIN, EEXIST, EIDRM, EINTR, EINVAL, ENOENT, ENOMSG, ENOSPC, ESRCH};
+use crate::mm::{copy_from_user, copy_to_user, translated_byte_buffer, UserBuffer};
+use crate::sync::{msg_queue, msg_queue_get, msg_queue_remove, MsgError, MSGMAX};
+use crate::task::{current_process, current_task, current_user_token, pid2process, MAIL_MAX_LEN};
+use alloc::vec::Vec;
+
+/// mail read syscall
+///
+/// Take the oldest message of the mailbox of the current process and copy at
+/// most `len` bytes of it to `buf`, the rest of the message is dropped.
+/// Return the number of bytes copied, or -EAGAIN if the mailbox is empty.
+/// With `len == 0` nothing is taken, return 0 if there is a message.
+pub fn sys_mail_read(buf: *mut u8, len: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_mail_read",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let token = current_user_token();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.mailbox.is_empty() {
+        return -EAGAIN;
+    }
+    if len == 0 {
+        return 0;
+    }
+    let message = inner.mailbox.pop().unwrap();
+    drop(inner);
+    let len = len.min(message.len());
+    let mut copied = 0;
+    for dst in translated_byte_buffer(token, buf, len) {
+        dst.copy_from_slice(&message[copied..copied + dst.len()]);
+        copied += dst.len();
+    }
+    copied as isize
+}
+
+/// mail write syscall
+///
+/// Send the first `len` bytes of `buf`, at most MAIL_MAX_LEN, to the mailbox
+/// of process `pid` without blocking.
+/// Return the number of bytes sent, -EAGAIN if the mailbox is full, or
+/// -ESRCH if there is no such process.
+/// With `len == 0` nothing is sent, return 0 if there is room for a message.
+pub fn sys_mail_write(pid: usize, buf: *const u8, len: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_mail_write",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let token = current_user_token();
+    let process = match pid2process(pid) {
+        Some(process) => process,
+        None => return -ESRCH,
+    };
+    let mut inner = process.inner_exclusive_access();
+    if inner.mailbox.is_full() {
+        return -EAGAIN;
+    }
+    if len == 0 {
+        return 0;
+    }
+    let mut message = Vec::new();
+    for src in translated_byte_buffer(token, buf, len.min(MAIL_MAX_LEN)) {
+        message.extend_from_slice(src);
+    }
+    let sent = message.len();
+    inner.mailbox.push(message);
+    sent as isize
+}
+
+/// Remove a message queue
+const IPC_RMID: usize = 0;
+
+fn msg_errno(err: MsgError) -> isize {
+    match err {
+        MsgError::Exists => -EEXIST,
+        MsgError::NotFound => -ENOENT,
+        MsgError::NoSpace => -ENOSPC,
+        MsgError::NoMessage => -ENOMSG,
+        MsgError::WouldBlock => -EAGAIN,
+        MsgError::TooBig => -E2BIG,
+        MsgError::Removed => -EIDRM,
+        MsgError::Interrupted => -EINTR,
+    }
+}
+
+/// msgget syscall
+///
+/// Return the id of the message queue of `key`. `msgflg` may have IPC_CREAT
+/// and IPC_EXCL, the permission bits are ignored.
+pub fn sys_msgget(key: usize, msgflg: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_msgget",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    match msg_queue_get(key, msgflg) {
+        Ok(id) => id as isize,
+        Err(err) => msg_errno(err),
+    }
+}
+
+/// msgsnd syscall
+///
+/// `msgp` points to the type of the message, a positive `usize`, followed by
+/// its text of `msgsz` bytes. `msgflg` may have IPC_NOWAIT.
+pub fn sys_msgsnd(msqid: usize, msgp: *const u8, msgsz: usize, msgflg: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_msgsnd",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let queue = match msg_queue(msqid) {
+        Some(queue) => queue,
+        None => return -EINVAL,
+    };
+    if msgsz > MSGMAX {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    let mtype: usize = copy_from_user(token, msgp as *const usize);
+    if (mtype as isize) < 1 {
+        return -EINVAL;
+    }
+    let mut text = Vec::with_capacity(msgsz);
+    let mtext = unsafe { msgp.add(core::mem::size_of::<usize>()) };
+    for buffer in translated_byte_buffer(token, mtext, msgsz) {
+        text.extend_from_slice(buffer);
+    }
+    match queue.send(mtype, text, msgflg) {
+        Ok(()) => 0,
+        Err(err) => msg_errno(err),
+    }
+}
+
+/// msgrcv syscall
+///
+/// Take the message selected by `msgtyp`, and copy its type and at most
+/// `msgsz` bytes of its text to `msgp` laid out as in msgsnd. `msgflg` may
+/// have IPC_NOWAIT, MSG_NOERROR and MSG_EXCEPT.
+/// Return the number of bytes of the text copied.
+pub fn sys_msgrcv(
+    msqid: usize,
+    msgp: *mut u8,
+    msgsz: usize,
+    msgtyp: isize,
+    msgflg: usize,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_msgrcv",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let queue = match msg_queue(msqid) {
+        Some(queue) => queue,
+        None => return -EINVAL,
+    };
+    let (mtype, text) = match queue.receive(msgtyp, msgsz, msgflg) {
+        Ok(message) => message,
+        Err(err) => return msg_errno(err),
+    };
+    let token = current_user_token();
+    copy_to_user(token, msgp as *mut usize, &mtype);
+    let mtext = unsafe { msgp.add(core::mem::size_of::<usize>()) };
+    UserBuffer::new(translated_byte_buffer(token, mtext, text.len())).write_bytes(&text) as isize
+}
+
+/// msgctl syscall, only IPC_RMID is supported
+///
+/// The waiters of a removed queue fail with -EIDRM.
+pub fn sys_msgctl(msqid: usize, cmd: usize, _buf: *mut u8) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_msgctl",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    match cmd {
+        IPC_RMID if msg_queue_remove(msqid) => 0,
+        _ => -EINVAL,
+    }
+}
@@ -20,32 +20,54 @@ pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
 /// unlinkat syscall
 pub const SYSCALL_UNLINKAT: usize = 35;
+/// mknodat syscall
+pub const SYSCALL_MKNODAT: usize = 33;
 /// linkat syscall
 pub const SYSCALL_LINKAT: usize = 37;
+/// mkdirat syscall
+pub const SYSCALL_MKDIRAT: usize = 34;
+/// chdir syscall
+pub const SYSCALL_CHDIR: usize = 49;
+/// getcwd syscall
+pub const SYSCALL_GETCWD: usize = 17;
 /// fstat syscall
 pub const SYSCALL_FSTAT: usize = 80;
+/// futex syscall
+pub const SYSCALL_FUTEX: usize = 98;
 /// exit syscall
 pub const SYSCALL_EXIT: usize = 93;
 /// sleep syscall
 pub const SYSCALL_SLEEP: usize = 101;
+/// getitimer syscall
+pub const SYSCALL_GETITIMER: usize = 102;
+/// setitimer syscall
+pub const SYSCALL_SETITIMER: usize = 103;
 /// yield syscall
 pub const SYSCALL_YIELD: usize = 124;
 /// kill syscall
 pub const SYSCALL_KILL: usize = 129;
-/*
 /// sigaction syscall
 pub const SYSCALL_SIGACTION: usize = 134;
 /// sigprocmask syscall
 pub const SYSCALL_SIGPROCMASK: usize = 135;
 /// sigreturn syscall
 pub const SYSCALL_SIGRETURN: usize = 139;
-*/
+/// times syscall
+pub const SYSCALL_TIMES: usize = 153;
+/// getrlimit syscall
+pub const SYSCALL_GETRLIMIT: usize = 163;
+/// setrlimit syscall
+pub const SYSCALL_SETRLIMIT: usize = 164;
+/// getrusage syscall
+pub const SYSCALL_GETRUSAGE: usize = 165;
 /// gettimeofday syscall
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 /// getpid syscall
 pub const SYSCALL_GETPID: usize = 172;
 /// gettid syscall
 pub const SYSCALL_GETTID: usize = 178;
+/// sysinfo syscall
+pub const SYSCALL_SYSINFO: usize = 179;
 /// fork syscall
 pub const SYSCALL_FORK: usize = 220;
 /// exec syscall
@@ -64,20 +86,54 @@ pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_MMAP: usize = 222;
 /// spawn syscall
 pub const SYSCALL_SPAWN: usize = 400;
-/*
 /// mail read syscall
 pub const SYSCALL_MAIL_READ: usize = 401;
 /// mail write syscall
 pub const SYSCALL_MAIL_WRITE: usize = 402;
-*/
+/// alarm syscall
+pub const SYSCALL_ALARM: usize = 403;
+/// poll syscall
+pub const SYSCALL_POLL: usize = 404;
+/// ppoll syscall
+pub const SYSCALL_PPOLL: usize = 73;
 /// dup syscall
 pub const SYSCALL_DUP: usize = 24;
 /// pipe syscall
 pub const SYSCALL_PIPE: usize = 59;
+/// fcntl syscall
+pub const SYSCALL_FCNTL: usize = 25;
+/// eventfd2 syscall
+pub const SYSCALL_EVENTFD2: usize = 19;
+/// signalfd4 syscall
+pub const SYSCALL_SIGNALFD4: usize = 74;
+/// socket syscall
+pub const SYSCALL_SOCKET: usize = 198;
+/// bind syscall
+pub const SYSCALL_BIND: usize = 200;
+/// listen syscall
+pub const SYSCALL_LISTEN: usize = 201;
+/// accept syscall
+pub const SYSCALL_ACCEPT: usize = 202;
+/// connect syscall
+pub const SYSCALL_CONNECT: usize = 203;
+/// sendto syscall
+pub const SYSCALL_SENDTO: usize = 206;
+/// recvfrom syscall
+pub const SYSCALL_RECVFROM: usize = 207;
+/// msgget syscall
+pub const SYSCALL_MSGGET: usize = 186;
+/// msgctl syscall
+pub const SYSCALL_MSGCTL: usize = 187;
+/// msgrcv syscall
+pub const SYSCALL_MSGRCV: usize = 188;
+/// msgsnd syscall
+pub const SYSCALL_MSGSND: usize = 189;
 /// task info syscall
 pub const SYSCALL_TASK_INFO: usize = 410;
 /// thread_create syscall
 pub const SYSCALL_THREAD_CREATE: usize = 460;
+/// thread_detach syscall
+pub const SYSCALL_THREAD_DETACH: usize = 461;
 /// waittid syscall
 pub const SYSCALL_WAITTID: usize = 462;
 /// mutex_create syscall
@@ -100,46 +156,153 @@ pub const SYSCALL_CONDVAR_CREATE: usize = 471;
 pub const SYSCALL_CONDVAR_SIGNAL: usize = 472;
 /// condvar_wait syscallca
 pub const SYSCALL_CONDVAR_WAIT: usize = 473;
+/// rwlock_create syscall
+pub const SYSCALL_RWLOCK_CREATE: usize = 474;
+/// rwlock_read_lock syscall
+pub const SYSCALL_RWLOCK_READ_LOCK: usize = 475;
+/// rwlock_write_lock syscall
+pub const SYSCALL_RWLOCK_WRITE_LOCK: usize = 476;
+/// rwlock_unlock syscall
+pub const SYSCALL_RWLOCK_UNLOCK: usize = 477;
+/// rwlock_destroy syscall
+pub const SYSCALL_RWLOCK_DESTROY: usize = 478;
+/// barrier_create syscall
+pub const SYSCALL_BARRIER_CREATE: usize = 479;
+/// barrier_wait syscall
+pub const SYSCALL_BARRIER_WAIT: usize = 480;
+/// barrier_destroy syscall
+pub const SYSCALL_BARRIER_DESTROY: usize = 481;
+/// condvar_broadcast syscall
+pub const SYSCALL_CONDVAR_BROADCAST: usize = 482;
+/// mutex_timedlock syscall
+pub const SYSCALL_MUTEX_TIMEDLOCK: usize = 483;
+/// semaphore_timeddown syscall
+pub const SYSCALL_SEMAPHORE_TIMEDDOWN: usize = 484;
+/// condvar_timedwait syscall
+pub const SYSCALL_CONDVAR_TIMEDWAIT: usize = 485;
+/// mutex_destroy syscall
+pub const SYSCALL_MUTEX_DESTROY: usize = 486;
+/// semaphore_destroy syscall
+pub const SYSCALL_SEMAPHORE_DESTROY: usize = 487;
+/// condvar_destroy syscall
+pub const SYSCALL_CONDVAR_DESTROY: usize = 488;
 
+mod errno;
 mod fs;
+mod ipc;
 mod process;
+mod socket;
 mod sync;
 mod thread;
 
 use fs::*;
+use ipc::*;
 use process::*;
+use socket::*;
 use sync::*;
 use thread::*;
 
 use crate::fs::Stat;
+use crate::task::{RLimit, SignalAction};
 
 /// handle syscall exception with `syscall_id` and other arguments
-pub fn syscall(syscall_id: usize, args: [usize; 4]) -> isize {
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
     match syscall_id {
         SYSCALL_DUP => sys_dup(args[0]),
         SYSCALL_LINKAT => sys_linkat(args[1] as *const u8, args[3] as *const u8),
-        SYSCALL_UNLINKAT => sys_unlinkat(args[1] as *const u8),
+        SYSCALL_UNLINKAT => sys_unlinkat(args[0] as isize, args[1] as *const u8, args[2] as u32),
+        SYSCALL_MKDIRAT => sys_mkdirat(args[0] as isize, args[1] as *const u8, args[2] as u32),
+        SYSCALL_CHDIR => sys_chdir(args[0] as *const u8),
+        SYSCALL_GETCWD => sys_getcwd(args[0] as *mut u8, args[1]),
+        SYSCALL_MKNODAT => sys_mknodat(
+            args[0] as isize,
+            args[1] as *const u8,
+            args[2] as u32,
+            args[3],
+        ),
         SYSCALL_OPENAT => sys_open(args[1] as *const u8, args[2] as u32),
         SYSCALL_CLOSE => sys_close(args[0]),
-        SYSCALL_PIPE => sys_pipe(args[0] as *mut usize),
+        SYSCALL_PIPE => sys_pipe(args[0] as *mut usize, args[1] as u32),
+        SYSCALL_FCNTL => sys_fcntl(args[0], args[1], args[2]),
+        SYSCALL_EVENTFD2 => sys_eventfd2(args[0] as u32, args[1] as u32),
+        SYSCALL_SIGNALFD4 => sys_signalfd4(
+            args[0] as isize,
+            args[1] as *const u32,
+            args[2],
+            args[3] as u32,
+        ),
+        SYSCALL_SOCKET => sys_socket(args[0], args[1], args[2]),
+        SYSCALL_BIND => sys_bind(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_LISTEN => sys_listen(args[0], args[1]),
+        SYSCALL_ACCEPT => sys_accept(args[0], args[1] as *mut u8, args[2] as *mut u32),
+        SYSCALL_CONNECT => sys_connect(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_SENDTO => sys_sendto(
+            args[0],
+            args[1] as *const u8,
+            args[2],
+            args[3],
+            args[4] as *const u8,
+            args[5],
+        ),
+        SYSCALL_MSGGET => sys_msgget(args[0], args[1]),
+        SYSCALL_MSGCTL => sys_msgctl(args[0], args[1], args[2] as *mut u8),
+        SYSCALL_MSGRCV => sys_msgrcv(
+            args[0],
+            args[1] as *mut u8,
+            args[2],
+            args[3] as isize,
+            args[4],
+        ),
+        SYSCALL_MSGSND => sys_msgsnd(args[0], args[1] as *const u8, args[2], args[3]),
+        SYSCALL_RECVFROM => sys_recvfrom(
+            args[0],
+            args[1] as *mut u8,
+            args[2],
+            args[3],
+            args[4] as *mut u8,
+            args[5] as *mut u32,
+        ),
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_FSTAT => sys_fstat(args[0], args[1] as *mut Stat),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_FUTEX => sys_futex(args[0], args[1], args[2], args[3], args[4], args[5]),
         SYSCALL_SLEEP => sys_sleep(args[0]),
+        SYSCALL_GETITIMER => sys_getitimer(args[0], args[1] as *mut ITimerVal),
+        SYSCALL_SETITIMER => sys_setitimer(
+            args[0],
+            args[1] as *const ITimerVal,
+            args[2] as *mut ITimerVal,
+        ),
+        SYSCALL_ALARM => sys_alarm(args[0]),
+        SYSCALL_POLL => sys_poll(args[0] as *mut PollFd, args[1], args[2] as isize),
+        SYSCALL_PPOLL => sys_ppoll(
+            args[0] as *mut PollFd,
+            args[1],
+            args[2] as *const TimeSpec,
+            args[3] as *const u32,
+        ),
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_GETPID => sys_getpid(),
         SYSCALL_GETTID => sys_gettid(),
         SYSCALL_FORK => sys_fork(),
         SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
-        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32, args[2]),
+        SYSCALL_GETRLIMIT => sys_getrlimit(args[0], args[1] as *mut RLimit),
+        SYSCALL_SETRLIMIT => sys_setrlimit(args[0], args[1] as *const RLimit),
+        SYSCALL_TIMES => sys_times(args[0] as *mut Tms),
+        SYSCALL_GETRUSAGE => sys_getrusage(args[0] as isize, args[1] as *mut RUsage),
+        SYSCALL_SYSINFO => sys_sysinfo(args[0] as *mut SysInfo),
         SYSCALL_GETTIMEOFDAY => sys_get_time(args[0] as *mut TimeVal, args[1]),
         SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2]),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
         SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
         SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
         SYSCALL_SPAWN => sys_spawn(args[0] as *const u8),
+        SYSCALL_MAIL_READ => sys_mail_read(args[0] as *mut u8, args[1]),
+        SYSCALL_MAIL_WRITE => sys_mail_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
+        SYSCALL_THREAD_DETACH => sys_thread_detach(args[0]),
         SYSCALL_WAITTID => sys_waittid(args[0]) as isize,
         SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] == 1),
         SYSCALL_MUTEX_LOCK => sys_mutex_lock(args[0]),
@@ -151,7 +314,31 @@ pub fn syscall(syscall_id: usize, args: [usize; 4]) -> isize {
         SYSCALL_CONDVAR_CREATE => sys_condvar_create(),
         SYSCALL_CONDVAR_SIGNAL => sys_condvar_signal(args[0]),
         SYSCALL_CONDVAR_WAIT => sys_condvar_wait(args[0], args[1]),
+        SYSCALL_RWLOCK_CREATE => sys_rwlock_create(),
+        SYSCALL_RWLOCK_READ_LOCK => sys_rwlock_read_lock(args[0]),
+        SYSCALL_RWLOCK_WRITE_LOCK => sys_rwlock_write_lock(args[0]),
+        SYSCALL_RWLOCK_UNLOCK => sys_rwlock_unlock(args[0]),
+        SYSCALL_RWLOCK_DESTROY => sys_rwlock_destroy(args[0]),
+        SYSCALL_BARRIER_CREATE => sys_barrier_create(args[0]),
+        SYSCALL_BARRIER_WAIT => sys_barrier_wait(args[0]),
+        SYSCALL_BARRIER_DESTROY => sys_barrier_destroy(args[0]),
+        SYSCALL_CONDVAR_BROADCAST => sys_condvar_broadcast(args[0]),
+        SYSCALL_MUTEX_TIMEDLOCK => sys_mutex_timedlock(args[0], args[1] as *const TimeSpec),
+        SYSCALL_SEMAPHORE_TIMEDDOWN => sys_semaphore_timeddown(args[0], args[1] as *const TimeSpec),
+        SYSCALL_CONDVAR_TIMEDWAIT => {
+            sys_condvar_timedwait(args[0], args[1], args[2] as *const TimeSpec)
+        }
+        SYSCALL_MUTEX_DESTROY => sys_mutex_destroy(args[0]),
+        SYSCALL_SEMAPHORE_DESTROY => sys_semaphore_destroy(args[0]),
+        SYSCALL_CONDVAR_DESTROY => sys_condvar_destroy(args[0]),
         SYSCALL_KILL => sys_kill(args[0], args[1] as u32),
+        SYSCALL_SIGACTION => sys_sigaction(
+            args[0],
+            args[1] as *const SignalAction,
+            args[2] as *mut SignalAction,
+        ),
+        SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0] as u32),
+        SYSCALL_SIGRETURN => sys_sigreturn(),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
 }
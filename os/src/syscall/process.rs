@@ -1,23 +1,116 @@
+use super::errno::EINTR;
+use super::fs::absolute_path;
 use crate::{
-    config::MAX_SYSCALL_NUM,
+    config::{MAX_SYSCALL_NUM, PAGE_SIZE},
     fs::{open_file, OpenFlags},
-    mm::{translated_ref, translated_refmut, translated_str},
+    mm::{
+        copy_from_user, copy_to_user, frame_usage, translated_ref, translated_refmut,
+        translated_str, MapPermission,
+    },
+    sync::update_priority,
     task::{
-        current_process, current_task, current_user_token, exit_current_and_run_next, pid2process,
-        suspend_current_and_run_next, SignalFlags, TaskStatus,
+        block_current_interruptible, current_process, current_task, current_user_token,
+        exit_current_and_run_next, get_itimer, pid2process, process_count, send_signal, set_itimer,
+        suspend_current_and_run_next, RLimit, SignalAction, SignalFlags, SignalFrame, TaskStatus,
+        ITIMER_COUNT, ITIMER_REAL, MAX_SIG, RLIMIT_AS,
     },
 };
 use alloc::{string::String, sync::Arc, vec::Vec};
 use crate::mm::{PageTable, PhysAddr, VirtAddr};
-use crate::timer::get_time_us;
+use crate::timer::{get_time_ms, get_time_us};
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
 }
 
+impl TimeVal {
+    fn from_us(us: usize) -> Self {
+        Self {
+            sec: us / 1_000_000,
+            usec: us % 1_000_000,
+        }
+    }
+    fn to_us(self) -> Option<usize> {
+        if self.usec >= 1_000_000 {
+            return None;
+        }
+        self.sec.checked_mul(1_000_000)?.checked_add(self.usec)
+    }
+}
+
+/// Setting of an interval timer
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ITimerVal {
+    /// the period, zero for a one-shot timer
+    pub it_interval: TimeVal,
+    /// the time until the next expiration, zero to disarm the timer
+    pub it_value: TimeVal,
+}
+
+/// Clock ticks per second, the unit of [`Tms`]
+pub const CLOCKS_PER_SEC: usize = 100;
+
+/// Process times, in clock ticks
+#[repr(C)]
+#[derive(Debug)]
+pub struct Tms {
+    /// user time of the process
+    pub tms_utime: usize,
+    /// kernel time of the process
+    pub tms_stime: usize,
+    /// user time of the reaped children
+    pub tms_cutime: usize,
+    /// kernel time of the reaped children
+    pub tms_cstime: usize,
+}
+
+/// Resource usage of the calling process itself
+pub const RUSAGE_SELF: isize = 0;
+/// Resource usage of the reaped children
+pub const RUSAGE_CHILDREN: isize = -1;
+/// Resource usage of the calling thread
+pub const RUSAGE_THREAD: isize = 1;
+
+/// Resource usage, only the cpu times are filled in
+#[repr(C)]
+#[derive(Debug)]
+pub struct RUsage {
+    /// user time
+    pub ru_utime: TimeVal,
+    /// kernel time
+    pub ru_stime: TimeVal,
+    /// the other fields of linux `struct rusage`, always zero
+    pub pad: [usize; 14],
+}
+
+/// System statistics, laid out as linux `struct sysinfo`
+#[repr(C)]
+#[derive(Debug)]
+pub struct SysInfo {
+    /// seconds since boot
+    pub uptime: isize,
+    /// load averages, always zero
+    pub loads: [usize; 3],
+    /// physical memory, in `mem_unit`
+    pub totalram: usize,
+    /// free physical memory, in `mem_unit`
+    pub freeram: usize,
+    /// shared, buffer and swap memory, always zero
+    pub pad: [usize; 4],
+    /// the number of processes
+    pub procs: u16,
+    /// high memory, always zero
+    pub totalhigh: usize,
+    /// free high memory, always zero
+    pub freehigh: usize,
+    /// the size of a memory unit in bytes, a page
+    pub mem_unit: u32,
+}
+
 /// Task information
 #[allow(dead_code)]
 pub struct TaskInfo {
@@ -31,7 +124,8 @@ pub struct TaskInfo {
 
 /// exit syscall
 ///
-/// exit the current task and run the next task in task list
+/// exit the current thread and run the next task in task list,
+/// the whole process exits only if the current thread is the main thread
 pub fn sys_exit(exit_code: i32) -> ! {
     trace!(
         "kernel:pid[{}] sys_exit",
@@ -83,7 +177,7 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
         current_task().unwrap().process.upgrade().unwrap().getpid()
     );
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = absolute_path(&translated_str(token, path));
     let mut args_vec: Vec<String> = Vec::new();
     loop {
         let arg_str_ptr = *translated_ref(token, args);
@@ -99,7 +193,9 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
         let all_data = app_inode.read_all();
         let process = current_process();
         let argc = args_vec.len();
-        process.exec(all_data.as_slice(), args_vec);
+        if !process.exec(all_data.as_slice(), args_vec) {
+            return -1;
+        }
         // return argc because cx.x[10] will be covered with it later
         argc as isize
     } else {
@@ -107,43 +203,67 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
     }
 }
 
+/// Return at once from waitpid if no child has exited yet
+pub const WNOHANG: usize = 1;
+
 /// waitpid syscall
 ///
 /// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// Else if there is a child process but it is still running, block until it
+/// exits, or return -2 at once if WNOHANG is set in `options`.
+/// Return -EINTR if a signal interrupts the wait.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
     //trace!("kernel: sys_waitpid");
     let process = current_process();
     // find a child process
-
-    let mut inner = process.inner_exclusive_access();
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
-    {
-        return -1;
+    loop {
+        let mut inner = process.inner_exclusive_access();
+        if !inner
+            .children
+            .iter()
+            .any(|p| pid == -1 || pid as usize == p.getpid())
+        {
+            return -1;
+            // ---- release current PCB
+        }
+        let pair = inner.children.iter().enumerate().find(|(_, p)| {
+            // ++++ temporarily access child PCB exclusively
+            p.inner_exclusive_access().is_zombie && (pid == -1 || pid as usize == p.getpid())
+            // ++++ release child PCB
+        });
+        if let Some((idx, _)) = pair {
+            let child = inner.children.remove(idx);
+            // confirm that child will be deallocated after being removed from children list
+            assert_eq!(Arc::strong_count(&child), 1);
+            let found_pid = child.getpid();
+            // ++++ temporarily access child PCB exclusively
+            let child_inner = child.inner_exclusive_access();
+            let exit_code = child_inner.exit_code;
+            inner.children_user_time_us +=
+                child_inner.user_time_us + child_inner.children_user_time_us;
+            inner.children_kernel_time_us +=
+                child_inner.kernel_time_us + child_inner.children_kernel_time_us;
+            drop(child_inner);
+            // ++++ release child PCB
+            *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
+            return found_pid as isize;
+        }
+        if options & WNOHANG != 0 {
+            return -2;
+        }
+        // wait for a child to exit, then look for it again
+        let task = current_task().unwrap();
+        inner.wait_queue.push_back(Arc::clone(&task));
+        drop(inner);
         // ---- release current PCB
+        if !block_current_interruptible() {
+            process
+                .inner_exclusive_access()
+                .wait_queue
+                .retain(|waiter| !Arc::ptr_eq(waiter, &task));
+            return -EINTR;
+        }
     }
-    let pair = inner.children.iter().enumerate().find(|(_, p)| {
-        // ++++ temporarily access child PCB exclusively
-        p.inner_exclusive_access().is_zombie && (pid == -1 || pid as usize == p.getpid())
-        // ++++ release child PCB
-    });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after being removed from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child PCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
-    } else {
-        -2
-    }
-    // ---- release current PCB automatically
 }
 
 /// kill syscall
@@ -154,7 +274,7 @@ pub fn sys_kill(pid: usize, signal: u32) -> isize {
     );
     if let Some(process) = pid2process(pid) {
         if let Some(flag) = SignalFlags::from_bits(signal) {
-            process.inner_exclusive_access().signals |= flag;
+            send_signal(&process, flag);
             0
         } else {
             -1
@@ -164,6 +284,93 @@ pub fn sys_kill(pid: usize, signal: u32) -> isize {
     }
 }
 
+/// sigaction syscall
+///
+/// set the action of `signum` to `*action` unless it is null, and store the
+/// old action to `*old_action` unless it is null
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_sigaction",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if signum == 0 || signum > MAX_SIG {
+        return -1;
+    }
+    // SIGKILL and SIGSTOP always take the default action
+    if SignalFlags::UNCATCHABLE.contains(SignalFlags::from_bits_truncate(1 << signum)) {
+        return -1;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let token = inner.get_user_token();
+    if !old_action.is_null() {
+        copy_to_user(token, old_action, &inner.signal_actions.table[signum]);
+    }
+    if !action.is_null() {
+        let mut new_action = copy_from_user(token, action);
+        new_action.mask = SignalFlags::from_bits_truncate(new_action.mask.bits());
+        inner.signal_actions.table[signum] = new_action;
+    }
+    0
+}
+
+/// sigprocmask syscall
+///
+/// set the signal mask of the current thread, and return the old one
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_sigprocmask",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let old_mask = task_inner.signal_mask;
+    // SIGKILL and SIGSTOP can not be blocked
+    task_inner.signal_mask = SignalFlags::from_bits_truncate(mask) - SignalFlags::UNCATCHABLE;
+    old_mask.bits() as isize
+}
+
+/// sigreturn syscall
+///
+/// return from a signal handler to the context interrupted by the signal
+pub fn sys_sigreturn() -> isize {
+    trace!(
+        "kernel:pid[{}] sys_sigreturn",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let task = current_task().unwrap();
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
+    let frame_va = task_inner.sigframe;
+    if frame_va == 0 {
+        return -1;
+    }
+    let start_vpn = VirtAddr::from(frame_va).floor();
+    let end_vpn = VirtAddr::from(frame_va + core::mem::size_of::<SignalFrame>()).ceil();
+    if !process_inner.memory_set.is_all_mapped(start_vpn, end_vpn) {
+        return -1;
+    }
+    let frame: SignalFrame = copy_from_user(
+        process_inner.get_user_token(),
+        frame_va as *const SignalFrame,
+    );
+    drop(process_inner);
+    // only the user registers and pc are restored, the kernel part of the
+    // trap context can not be changed by the user
+    let trap_cx = task_inner.get_trap_cx();
+    trap_cx.x = frame.x;
+    trap_cx.sepc = frame.sepc;
+    task_inner.signal_mask = SignalFlags::from_bits_truncate(frame.mask);
+    task_inner.sigframe = frame.prev;
+    // the return value is written to a0, so return the one restored
+    trap_cx.x[10] as isize
+}
+
 
 /// transform addr from virtual to physical
 pub fn vir_to_phy(virtual_addr: VirtAddr) -> PhysAddr {
@@ -200,6 +407,155 @@ pub fn sys_get_time(_ts: *mut TimeVal, _tz: usize) -> isize {
     0
 }
 
+/// getitimer syscall
+pub fn sys_getitimer(which: usize, curr_value: *mut ITimerVal) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_getitimer",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if which >= ITIMER_COUNT {
+        return -1;
+    }
+    let (interval_us, remaining_us) = get_itimer(&current_process(), which);
+    let value = ITimerVal {
+        it_interval: TimeVal::from_us(interval_us),
+        it_value: TimeVal::from_us(remaining_us),
+    };
+    copy_to_user(current_user_token(), curr_value, &value);
+    0
+}
+
+/// setitimer syscall
+///
+/// arm or disarm an interval timer, and store the old setting to
+/// `*old_value` unless it is null
+pub fn sys_setitimer(
+    which: usize,
+    new_value: *const ITimerVal,
+    old_value: *mut ITimerVal,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_setitimer",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if which >= ITIMER_COUNT || new_value.is_null() {
+        return -1;
+    }
+    let token = current_user_token();
+    let new_value = copy_from_user(token, new_value);
+    let (interval_us, value_us) =
+        match (new_value.it_interval.to_us(), new_value.it_value.to_us()) {
+            (Some(interval_us), Some(value_us)) => (interval_us, value_us),
+            _ => return -1,
+        };
+    let (old_interval_us, old_remaining_us) =
+        set_itimer(&current_process(), which, interval_us, value_us);
+    if !old_value.is_null() {
+        let value = ITimerVal {
+            it_interval: TimeVal::from_us(old_interval_us),
+            it_value: TimeVal::from_us(old_remaining_us),
+        };
+        copy_to_user(token, old_value, &value);
+    }
+    0
+}
+
+/// alarm syscall
+///
+/// post SIGALRM to the current process after `seconds`, or cancel the alarm
+/// if it is 0. Return the seconds left of the previous alarm.
+pub fn sys_alarm(seconds: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_alarm",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let value_us = match seconds.checked_mul(1_000_000) {
+        Some(value_us) => value_us,
+        None => return -1,
+    };
+    let (_, old_remaining_us) = set_itimer(&current_process(), ITIMER_REAL, 0, value_us);
+    ((old_remaining_us + 999_999) / 1_000_000) as isize
+}
+
+/// times syscall
+///
+/// fill in the cpu times of the current process and its reaped children, and
+/// return the clock ticks elapsed since boot
+pub fn sys_times(tms: *mut Tms) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_times",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let (user_time_us, kernel_time_us) = inner.cpu_time_us();
+    let us_per_clock = 1_000_000 / CLOCKS_PER_SEC;
+    let value = Tms {
+        tms_utime: user_time_us / us_per_clock,
+        tms_stime: kernel_time_us / us_per_clock,
+        tms_cutime: inner.children_user_time_us / us_per_clock,
+        tms_cstime: inner.children_kernel_time_us / us_per_clock,
+    };
+    copy_to_user(inner.get_user_token(), tms, &value);
+    (get_time_ms() / (1000 / CLOCKS_PER_SEC)) as isize
+}
+
+/// getrusage syscall
+///
+/// `who` is one of RUSAGE_SELF, RUSAGE_CHILDREN and RUSAGE_THREAD
+pub fn sys_getrusage(who: isize, usage: *mut RUsage) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_getrusage",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let (user_time_us, kernel_time_us) = match who {
+        RUSAGE_SELF => current_process().inner_exclusive_access().cpu_time_us(),
+        RUSAGE_CHILDREN => {
+            let process = current_process();
+            let inner = process.inner_exclusive_access();
+            (inner.children_user_time_us, inner.children_kernel_time_us)
+        }
+        RUSAGE_THREAD => {
+            let task = current_task().unwrap();
+            let task_inner = task.inner_exclusive_access();
+            (task_inner.user_time_us, task_inner.kernel_time_us)
+        }
+        _ => return -1,
+    };
+    let value = RUsage {
+        ru_utime: TimeVal::from_us(user_time_us),
+        ru_stime: TimeVal::from_us(kernel_time_us),
+        pad: [0; 14],
+    };
+    copy_to_user(current_user_token(), usage, &value);
+    0
+}
+
+/// sysinfo syscall
+///
+/// The memory is counted in physical page frames, which a test may compare
+/// to make sure that the exited processes give back all of them.
+pub fn sys_sysinfo(info: *mut SysInfo) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_sysinfo",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let (totalram, freeram) = frame_usage();
+    let value = SysInfo {
+        uptime: (get_time_ms() / 1000) as isize,
+        loads: [0; 3],
+        totalram,
+        freeram,
+        pad: [0; 4],
+        procs: process_count() as u16,
+        totalhigh: 0,
+        freehigh: 0,
+        mem_unit: PAGE_SIZE as u32,
+    };
+    copy_to_user(current_user_token(), info, &value);
+    0
+}
+
 /// task_info syscall
 ///
 /// YOUR JOB: Finish sys_task_info to pass testcases
@@ -215,24 +571,101 @@ pub fn sys_task_info(_ti: *mut TaskInfo) -> isize {
 
 /// mmap syscall
 ///
-/// YOUR JOB: Implement mmap.
-pub fn sys_mmap(_start: usize, _len: usize, _port: usize) -> isize {
+/// map [start, start + len) with permission `port`(bit 0: R, bit 1: W, bit 2: X),
+/// `start` must be page aligned and none of the pages may be mapped already.
+/// The address space can not grow beyond RLIMIT_AS.
+pub fn sys_mmap(start: usize, len: usize, port: usize) -> isize {
     trace!(
-        "kernel:pid[{}] sys_mmap NOT IMPLEMENTED",
+        "kernel:pid[{}] sys_mmap",
         current_task().unwrap().process.upgrade().unwrap().getpid()
     );
-    -1
+    let start_va = VirtAddr::from(start);
+    if !start_va.aligned() || len == 0 || port & !0x7 != 0 || port & 0x7 == 0 {
+        return -1;
+    }
+    let end_va = VirtAddr::from(start + len);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let size = (end_va.ceil().0 - start_va.floor().0) * PAGE_SIZE;
+    if inner.memory_set.mapped_size() + size > inner.rlimits.cur(RLIMIT_AS) {
+        return -1;
+    }
+    if inner.memory_set.is_mapped(start_va.floor(), end_va.ceil()) {
+        return -1;
+    }
+    let mut permission = MapPermission::U;
+    if port & 0x1 != 0 {
+        permission |= MapPermission::R;
+    }
+    if port & 0x2 != 0 {
+        permission |= MapPermission::W;
+    }
+    if port & 0x4 != 0 {
+        permission |= MapPermission::X;
+    }
+    inner
+        .memory_set
+        .insert_framed_area(start_va, end_va, permission);
+    0
 }
 
 /// munmap syscall
 ///
-/// YOUR JOB: Implement munmap.
-pub fn sys_munmap(_start: usize, _len: usize) -> isize {
+/// unmap [start, start + len), which must be exactly an area mapped by mmap
+pub fn sys_munmap(start: usize, len: usize) -> isize {
     trace!(
-        "kernel:pid[{}] sys_munmap NOT IMPLEMENTED",
+        "kernel:pid[{}] sys_munmap",
         current_task().unwrap().process.upgrade().unwrap().getpid()
     );
-    -1
+    let start_va = VirtAddr::from(start);
+    if !start_va.aligned() || len == 0 {
+        return -1;
+    }
+    let end_va = VirtAddr::from(start + len);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner
+        .memory_set
+        .remove_user_area(start_va.floor(), end_va.ceil())
+    {
+        0
+    } else {
+        -1
+    }
+}
+
+/// getrlimit syscall
+pub fn sys_getrlimit(resource: usize, rlim: *mut RLimit) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_getrlimit",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if let Some(limit) = inner.rlimits.get(resource) {
+        copy_to_user(inner.get_user_token(), rlim, &limit);
+        0
+    } else {
+        -1
+    }
+}
+
+/// setrlimit syscall
+///
+/// the soft limit can not exceed the hard limit, and the hard limit can only be lowered
+pub fn sys_setrlimit(resource: usize, rlim: *const RLimit) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_setrlimit",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let limit = copy_from_user(inner.get_user_token(), rlim);
+    if inner.rlimits.set(resource, limit) {
+        0
+    } else {
+        -1
+    }
 }
 
 /// change data segment size
@@ -257,11 +690,18 @@ pub fn sys_spawn(_path: *const u8) -> isize {
 
 /// set priority syscall
 ///
-/// YOUR JOB: Set task priority
-pub fn sys_set_priority(_prio: isize) -> isize {
+/// Set the priority of the current thread, at least 2, and return it.
+/// A priority inherited through a blocking mutex is kept until it unlocks.
+pub fn sys_set_priority(prio: isize) -> isize {
     trace!(
-        "kernel:pid[{}] sys_set_priority NOT IMPLEMENTED",
+        "kernel:pid[{}] sys_set_priority",
         current_task().unwrap().process.upgrade().unwrap().getpid()
     );
-    -1
+    if prio < 2 {
+        return -1;
+    }
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().priority = prio as usize;
+    update_priority(&task);
+    prio
 }
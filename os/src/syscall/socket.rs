@@ -0,0 +1,296 @@
+//! Unix-domain socket syscalls
+//!
+//! The address of a socket is a `sockaddr_un` naming a socket file of
+//! easy-fs.
+
+use super::errno::{
+    EADDRINUSE, EAFNOSUPPORT, EAGAIN, EBADF, ECONNREFUSED, EFAULT, EINTR, EINVAL, EISCONN, EMFILE,
+    EMSGSIZE, ENOENT, ENOTCONN, ENOTSOCK, EOPNOTSUPP, EPROTONOSUPPORT,
+};
+use super::fs::{absolute_path, fs_errno};
+use crate::fs::{File, OpenFlags, Socket, SocketError, SocketType};
+use crate::mm::{copy_from_user, copy_to_user, translated_byte_buffer, UserBuffer};
+use crate::task::{current_process, current_task, current_user_token};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// The address family of Unix-domain sockets
+const AF_UNIX: usize = 1;
+/// A connected byte stream socket
+const SOCK_STREAM: usize = 1;
+/// A datagram socket
+const SOCK_DGRAM: usize = 2;
+/// The size of `sockaddr_un`, a family of 2 bytes and a path of 108
+const SOCKADDR_UN_SIZE: usize = 110;
+
+fn socket_errno(err: SocketError) -> isize {
+    match err {
+        SocketError::AddrInUse => -EADDRINUSE,
+        SocketError::NoEntry => -ENOENT,
+        SocketError::ConnRefused => -ECONNREFUSED,
+        SocketError::NotConnected => -ENOTCONN,
+        SocketError::IsConnected => -EISCONN,
+        SocketError::Invalid => -EINVAL,
+        SocketError::OpNotSupp => -EOPNOTSUPP,
+        SocketError::MsgSize => -EMSGSIZE,
+        SocketError::WouldBlock => -EAGAIN,
+        SocketError::Interrupted => -EINTR,
+        SocketError::Fs(err) => fs_errno(err),
+    }
+}
+
+/// Get the file of `fd`, which has to be a socket
+fn socket_file(fd: usize) -> Result<Arc<dyn File + Send + Sync>, isize> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => Arc::clone(file),
+        _ => return Err(-EBADF),
+    };
+    drop(inner);
+    if file.as_socket().is_none() {
+        return Err(-ENOTSOCK);
+    }
+    Ok(file)
+}
+
+/// Read the path of the `sockaddr_un` of `addrlen` bytes at `addr`, made
+/// absolute from the current directory
+fn read_sockaddr(addr: *const u8, addrlen: usize) -> Result<String, isize> {
+    if addr.is_null() {
+        return Err(-EFAULT);
+    }
+    if addrlen <= 2 || addrlen > SOCKADDR_UN_SIZE {
+        return Err(-EINVAL);
+    }
+    let mut bytes = Vec::new();
+    for buffer in translated_byte_buffer(current_user_token(), addr, addrlen) {
+        bytes.extend_from_slice(buffer);
+    }
+    if u16::from_ne_bytes([bytes[0], bytes[1]]) as usize != AF_UNIX {
+        return Err(-EINVAL);
+    }
+    // the path ends at a NUL or at the end of the address
+    let path = &bytes[2..];
+    let len = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+    if len == 0 {
+        return Err(-EINVAL);
+    }
+    let path = String::from_utf8(path[..len].to_vec()).map_err(|_| -EINVAL)?;
+    Ok(absolute_path(&path))
+}
+
+/// Write a `sockaddr_un` of `path` to `addr`, truncated to `*addrlen` bytes,
+/// and its full length to `*addrlen`. Nothing is written if `addr` is null.
+fn write_sockaddr(addr: *mut u8, addrlen: *mut u32, path: Option<&str>) {
+    if addr.is_null() || addrlen.is_null() {
+        return;
+    }
+    let token = current_user_token();
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(AF_UNIX as u16).to_ne_bytes());
+    // an unbound socket has an unnamed address of the family only
+    if let Some(path) = path {
+        bytes.extend_from_slice(path.as_bytes());
+        bytes.push(0);
+    }
+    let len = bytes.len().min(copy_from_user(token, addrlen) as usize);
+    UserBuffer::new(translated_byte_buffer(token, addr, len)).write_bytes(&bytes[..len]);
+    copy_to_user(token, addrlen, &(bytes.len() as u32));
+}
+
+/// Install `socket` in the fd table of the current process
+fn alloc_socket_fd(socket: Socket) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.alloc_fd() {
+        Some(fd) => {
+            inner.fd_table[fd] = Some(Arc::new(socket));
+            fd as isize
+        }
+        None => -EMFILE,
+    }
+}
+
+/// socket syscall
+///
+/// Only AF_UNIX sockets of SOCK_STREAM or SOCK_DGRAM are supported, `type_`
+/// may have O_NONBLOCK.
+pub fn sys_socket(domain: usize, type_: usize, protocol: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_socket",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if domain != AF_UNIX {
+        return -EAFNOSUPPORT;
+    }
+    if protocol != 0 {
+        return -EPROTONOSUPPORT;
+    }
+    let nonblock = OpenFlags::NONBLOCK.bits() as usize;
+    let socket = match type_ & !nonblock {
+        SOCK_STREAM => Socket::new(SocketType::Stream),
+        SOCK_DGRAM => Socket::new(SocketType::Datagram),
+        _ => return -EINVAL,
+    };
+    socket.set_nonblocking(type_ & nonblock != 0);
+    alloc_socket_fd(socket)
+}
+
+/// bind syscall, the socket file is created at the address
+pub fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_bind",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(errno) => return errno,
+    };
+    let path = match read_sockaddr(addr, addrlen) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    match file.as_socket().unwrap().bind(path.as_str()) {
+        Ok(()) => 0,
+        Err(err) => socket_errno(err),
+    }
+}
+
+/// listen syscall
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_listen",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(errno) => return errno,
+    };
+    match file.as_socket().unwrap().listen(backlog) {
+        Ok(()) => 0,
+        Err(err) => socket_errno(err),
+    }
+}
+
+/// accept syscall
+///
+/// Return the fd of the connected socket. The address of the peer is
+/// reported unnamed.
+pub fn sys_accept(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_accept",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(errno) => return errno,
+    };
+    let socket = file.as_socket().unwrap();
+    if let Err(err) = socket.wait_connection() {
+        return socket_errno(err);
+    }
+    // the connection stays queued if there is no fd for it
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let new_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -EMFILE,
+    };
+    inner.fd_table[new_fd] = Some(Arc::new(socket.take_connection().unwrap()));
+    drop(inner);
+    write_sockaddr(addr, addrlen, None);
+    new_fd as isize
+}
+
+/// connect syscall
+pub fn sys_connect(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_connect",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(errno) => return errno,
+    };
+    let path = match read_sockaddr(addr, addrlen) {
+        Ok(path) => path,
+        Err(errno) => return errno,
+    };
+    match file.as_socket().unwrap().connect(path.as_str()) {
+        Ok(()) => 0,
+        Err(err) => socket_errno(err),
+    }
+}
+
+/// sendto syscall
+///
+/// Send to `dest_addr` unless it is null, then the socket has to be
+/// connected. `flags` has to be 0.
+pub fn sys_sendto(
+    fd: usize,
+    buf: *const u8,
+    len: usize,
+    flags: usize,
+    dest_addr: *const u8,
+    addrlen: usize,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_sendto",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if flags != 0 {
+        return -EINVAL;
+    }
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(errno) => return errno,
+    };
+    let path = if dest_addr.is_null() {
+        None
+    } else {
+        match read_sockaddr(dest_addr, addrlen) {
+            Ok(path) => Some(path),
+            Err(errno) => return errno,
+        }
+    };
+    let buf = UserBuffer::new(translated_byte_buffer(current_user_token(), buf, len));
+    match file.as_socket().unwrap().send_to(buf, path.as_deref()) {
+        Ok(len) => len as isize,
+        Err(err) => socket_errno(err),
+    }
+}
+
+/// recvfrom syscall
+///
+/// The address of the sender of a datagram is written to `src_addr` unless
+/// it is null. `flags` has to be 0.
+pub fn sys_recvfrom(
+    fd: usize,
+    buf: *mut u8,
+    len: usize,
+    flags: usize,
+    src_addr: *mut u8,
+    addrlen: *mut u32,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_recvfrom",
+        current_task().unwrap().process.upgrade().unwrap().getpid()
+    );
+    if flags != 0 {
+        return -EINVAL;
+    }
+    let file = match socket_file(fd) {
+        Ok(file) => file,
+        Err(errno) => return errno,
+    };
+    let buf = UserBuffer::new(translated_byte_buffer(current_user_token(), buf, len));
+    match file.as_socket().unwrap().recv_from(buf) {
+        Ok((len, from)) => {
+            write_sockaddr(src_addr, addrlen, from.as_deref());
+            len as isize
+        }
+        Err(err) => socket_errno(err),
+    }
+}
@@ -1,8 +1,16 @@
-use crate::sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
-use crate::task::{block_current_and_run_next, current_process, current_task};
-use crate::timer::{add_timer, get_time_ms};
+use super::errno::{EAGAIN, EBUSY, EFAULT, EINTR, EINVAL, ENOSYS, EOWNERDEAD, EPERM, ETIMEDOUT};
+use crate::mm::{copy_from_user, PageTable, VirtAddr};
+use crate::sync::{
+    futex_enqueue, futex_remove_task, futex_requeue, futex_wake, Barrier, Condvar, Mutex,
+    MutexBlocking, MutexSpin, RwLock, Semaphore, RWLOCK_MAX_READERS,
+};
+use crate::task::{
+    block_current_and_run_next, block_current_interruptible, current_interrupted, current_process,
+    current_task, current_user_token,
+};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
 use alloc::sync::Arc;
-use alloc::vec;
+use alloc::vec::Vec;
 
 /// sleep syscall
 pub fn sys_sleep(ms: usize) -> isize {
@@ -24,6 +32,129 @@ pub fn sys_sleep(ms: usize) -> isize {
     0
 }
 
+/// Make sure `row` of allocation / need has a column for resource `id`
+fn ensure_column(row: &mut Vec<usize>, id: usize) {
+    if row.len() <= id {
+        row.resize(id + 1, 0);
+    }
+}
+
+/// Time in seconds and nanoseconds
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TimeSpec {
+    /// seconds
+    pub tv_sec: usize,
+    /// nanoseconds, less than 1_000_000_000
+    pub tv_nsec: usize,
+}
+
+/// Read a relative timeout from user space and turn it into the time it
+/// expires in milliseconds
+pub fn read_timeout(token: usize, timeout: *const TimeSpec) -> Result<usize, isize> {
+    if timeout.is_null() {
+        return Err(-EFAULT);
+    }
+    let timeout = copy_from_user(token, timeout);
+    if timeout.tv_nsec >= 1_000_000_000 {
+        return Err(-EINVAL);
+    }
+    Ok(get_time_ms() + timeout.tv_sec * 1000 + timeout.tv_nsec / 1_000_000)
+}
+
+/// Block while the futex word equals the value given
+const FUTEX_WAIT: usize = 0;
+/// Wake up waiters of the futex word
+const FUTEX_WAKE: usize = 1;
+/// Wake up waiters and move the others to another futex word
+const FUTEX_REQUEUE: usize = 3;
+/// FUTEX_REQUEUE only if the futex word equals the value given
+const FUTEX_CMP_REQUEUE: usize = 4;
+/// The futex is private to the process, which makes no difference here
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+/// Translate the address of a futex word to the physical address as its key
+fn futex_pa(token: usize, uaddr: usize) -> Result<usize, isize> {
+    if uaddr % core::mem::size_of::<u32>() != 0 {
+        return Err(-EINVAL);
+    }
+    PageTable::from_token(token)
+        .translate_va(VirtAddr::from(uaddr))
+        .map(|pa| pa.0)
+        .ok_or(-EFAULT)
+}
+
+/// futex syscall
+///
+/// FUTEX_WAIT blocks while `*uaddr == val` until it is woken up, or until
+/// `timeout` expires if it is not null, or until a signal interrupts it.
+/// FUTEX_WAKE wakes up at most `val` waiters.
+/// FUTEX_REQUEUE wakes up at most `val` waiters and moves at most `val2` of
+/// the others to `uaddr2`, where `val2` is passed in place of `timeout`.
+/// FUTEX_CMP_REQUEUE does the same only if `*uaddr == val3`.
+pub fn sys_futex(
+    uaddr: usize,
+    op: usize,
+    val: usize,
+    timeout: usize,
+    uaddr2: usize,
+    val3: usize,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_futex",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let token = current_user_token();
+    let pa = match futex_pa(token, uaddr) {
+        Ok(pa) => pa,
+        Err(errno) => return errno,
+    };
+    let futex_word = || unsafe { *(pa as *const u32) };
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            if futex_word() != val as u32 {
+                return -EAGAIN;
+            }
+            let task = current_task().unwrap();
+            if timeout != 0 {
+                match read_timeout(token, timeout as *const TimeSpec) {
+                    Ok(expire_ms) => add_timer(expire_ms, Arc::clone(&task)),
+                    Err(errno) => return errno,
+                }
+            }
+            futex_enqueue(pa, Arc::clone(&task));
+            let woken = block_current_interruptible();
+            if timeout != 0 {
+                remove_timer(Arc::clone(&task));
+            }
+            // still queued means it is the timer or a signal who woke us up
+            if futex_remove_task(&task) {
+                return if woken { -ETIMEDOUT } else { -EINTR };
+            }
+            0
+        }
+        FUTEX_WAKE => futex_wake(pa, val) as isize,
+        FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
+            let pa2 = match futex_pa(token, uaddr2) {
+                Ok(pa2) => pa2,
+                Err(errno) => return errno,
+            };
+            if op & !FUTEX_PRIVATE_FLAG == FUTEX_CMP_REQUEUE && futex_word() != val3 as u32 {
+                return -EAGAIN;
+            }
+            futex_requeue(pa, val, pa2, timeout) as isize
+        }
+        _ => -ENOSYS,
+    }
+}
+
 /// mutex create syscall
 pub fn sys_mutex_create(blocking: bool) -> isize {
     trace!(
@@ -65,12 +196,27 @@ pub fn sys_mutex_create(blocking: bool) -> isize {
         mutex_available.push(0);
     }
     mutex_available[ret as usize] = 1;
+    // a reused id starts with a clean column
+    let id = ret as usize;
+    let inner = &mut *process_inner;
+    for row in inner
+        .mutex_allocation
+        .iter_mut()
+        .chain(inner.mutex_need.iter_mut())
+    {
+        if let Some(count) = row.get_mut(id) {
+            *count = 0;
+        }
+    }
 
     drop(process_inner);
     ret
 }
 
 /// mutex lock syscall
+///
+/// return -0xDEAD if deadlock detection is enabled and the request is unsafe,
+/// -EINTR if a signal interrupts the wait
 pub fn sys_mutex_lock(mutex_id: usize) -> isize {
     trace!(
         "kernel:pid[{}] tid[{}] sys_mutex_lock",
@@ -83,58 +229,82 @@ pub fn sys_mutex_lock(mutex_id: usize) -> isize {
             .unwrap()
             .tid
     );
+    mutex_lock(mutex_id, None)
+}
+
+/// mutex lock syscall giving up after `timeout`
+///
+/// return -ETIMEDOUT if the mutex is not locked in time
+pub fn sys_mutex_timedlock(mutex_id: usize, timeout: *const TimeSpec) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_mutex_timedlock",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    match read_timeout(current_user_token(), timeout) {
+        Ok(expire_ms) => mutex_lock(mutex_id, Some(expire_ms)),
+        Err(errno) => errno,
+    }
+}
+
+/// Lock a mutex, giving up at `expire_ms` if given, or when a signal
+/// interrupts the wait
+fn mutex_lock(mutex_id: usize, expire_ms: Option<usize>) -> isize {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return -EINVAL,
+    };
 
-    // update mutex_need / mutex_allocation
+    // request the mutex
     let cur_tid = current_task().unwrap().inner_exclusive_access().res.as_ref().unwrap().tid;
-    for tid in 0..process_inner.tasks.len() {
-        while process_inner.mutex_need[tid].len() < (mutex_id + 1) {
-            process_inner.mutex_need[tid].push(0);
-            process_inner.mutex_allocation[tid].push(0);
-        }
-        // 请求加锁, 需求+1
-        if cur_tid == tid {
-            process_inner.mutex_need[tid][mutex_id] += 1;
-        }
-    }
+    ensure_column(&mut process_inner.mutex_need[cur_tid], mutex_id);
+    process_inner.mutex_need[cur_tid][mutex_id] += 1;
 
     // deadlock detect
-    if process_inner.deadlock_detect {
-        let mut work = process_inner.mutex_available.clone();
-        let mut finish = vec![false; process_inner.tasks.len()];
-
-        // 是否找到符合条件的线程
-        let mut is_found = false;
-        for tid in 0..process_inner.tasks.len() {
-            let need = &process_inner.mutex_need;
-            if finish[tid] == false && need[tid][mutex_id] <= work[mutex_id] {
-                let allocation = &process_inner.mutex_allocation;
-                work[mutex_id] += allocation[tid][mutex_id];
-                finish[tid] = true;
-                is_found = true;
-            }
-        }
-        if !is_found {
-            // 出现死锁
-            if !finish.iter().all(|&value| value) {
-                return -0xDEAD;
-            }
-        }
+    if process_inner.deadlock_detect && !process_inner.is_safe_state() {
+        process_inner.mutex_need[cur_tid][mutex_id] -= 1;
+        return -0xDEAD;
     }
 
+    drop(process_inner);
+    drop(process);
+    let locked = mutex.lock_timeout(expire_ms);
+
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    if !locked {
+        // withdraw the request
+        process_inner.mutex_need[cur_tid][mutex_id] -= 1;
+        drop(process_inner);
+        return if current_interrupted() {
+            -EINTR
+        } else {
+            -ETIMEDOUT
+        };
+    }
+    // the mutex is granted
     process_inner.mutex_available[mutex_id] -= 1;
+    ensure_column(&mut process_inner.mutex_allocation[cur_tid], mutex_id);
     process_inner.mutex_allocation[cur_tid][mutex_id] += 1;
     process_inner.mutex_need[cur_tid][mutex_id] -= 1;
-
-    drop(process_inner);
-    drop(process);
-    mutex.lock();
+    // the mutex is locked anyway, but what it protects may be inconsistent
+    if mutex.take_owner_dead() {
+        return -EOWNERDEAD;
+    }
     0
 }
 
 /// mutex unlock syscall
+///
+/// return -EPERM if the mutex is not held by the current thread
 pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
     trace!(
         "kernel:pid[{}] tid[{}] sys_mutex_unlock",
@@ -149,11 +319,20 @@ pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
     );
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return -EINVAL,
+    };
+    if !mutex.is_owner(&current_task().unwrap()) {
+        return -EPERM;
+    }
 
     let cur_tid = current_task().unwrap().inner_exclusive_access().res.as_ref().unwrap().tid;
     process_inner.mutex_available[mutex_id] += 1;
-    process_inner.mutex_allocation[cur_tid][mutex_id] -= 1;
+    ensure_column(&mut process_inner.mutex_allocation[cur_tid], mutex_id);
+    if process_inner.mutex_allocation[cur_tid][mutex_id] > 0 {
+        process_inner.mutex_allocation[cur_tid][mutex_id] -= 1;
+    }
 
     drop(process_inner);
     drop(process);
@@ -161,7 +340,6 @@ pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
     0
 }
 
-
 /// semaphore create syscall
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     trace!(
@@ -200,14 +378,15 @@ pub fn sys_semaphore_create(res_count: usize) -> isize {
     }
     available[id] = res_count;
 
-    // init need / allocation
-    for tid in 0..process_inner.tasks.len() {
-        while process_inner.semaphore_allocation[tid].len() < (id + 1) {
-            process_inner.semaphore_allocation[tid].push(0);
-        }
-        while process_inner.semaphore_need[tid].len() < (id + 1) {
-            process_inner.semaphore_need[tid].push(0);
-        }
+    // init need / allocation, a reused id starts with a clean column
+    let inner = &mut *process_inner;
+    for row in inner
+        .semaphore_allocation
+        .iter_mut()
+        .chain(inner.semaphore_need.iter_mut())
+    {
+        ensure_column(row, id);
+        row[id] = 0;
     }
 
     drop(process_inner);
@@ -229,14 +408,18 @@ pub fn sys_semaphore_up(sem_id: usize) -> isize {
     );
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    let sem = match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) => Arc::clone(sem),
+        _ => return -EINVAL,
+    };
 
     let cur_tid = current_task().unwrap().inner_exclusive_access().res.as_ref().unwrap().tid;
     process_inner.semaphore_available[sem_id] += 1;
-    while process_inner.semaphore_allocation[cur_tid].len() < sem_id + 1 {
-        process_inner.semaphore_allocation[cur_tid].push(0);
+    ensure_column(&mut process_inner.semaphore_allocation[cur_tid], sem_id);
+    // a thread may release a unit it never acquired, e.g. a producer
+    if process_inner.semaphore_allocation[cur_tid][sem_id] > 0 {
+        process_inner.semaphore_allocation[cur_tid][sem_id] -= 1;
     }
-    process_inner.semaphore_allocation[cur_tid][sem_id] -= 1;
 
     drop(process_inner);
     sem.up();
@@ -244,6 +427,9 @@ pub fn sys_semaphore_up(sem_id: usize) -> isize {
 }
 
 /// semaphore down syscall
+///
+/// return -0xDEAD if deadlock detection is enabled and the request is unsafe,
+/// -EINTR if a signal interrupts the wait
 pub fn sys_semaphore_down(sem_id: usize) -> isize {
     trace!(
         "kernel:pid[{}] tid[{}] sys_semaphore_down",
@@ -256,71 +442,72 @@ pub fn sys_semaphore_down(sem_id: usize) -> isize {
             .unwrap()
             .tid
     );
+    semaphore_down(sem_id, None)
+}
+
+/// semaphore down syscall giving up after `timeout`
+///
+/// return -ETIMEDOUT if no unit is acquired in time
+pub fn sys_semaphore_timeddown(sem_id: usize, timeout: *const TimeSpec) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_semaphore_timeddown",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    match read_timeout(current_user_token(), timeout) {
+        Ok(expire_ms) => semaphore_down(sem_id, Some(expire_ms)),
+        Err(errno) => errno,
+    }
+}
+
+/// Take a unit of a semaphore, giving up at `expire_ms` if given, or when a
+/// signal interrupts the wait
+fn semaphore_down(sem_id: usize, expire_ms: Option<usize>) -> isize {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    let sem = match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) => Arc::clone(sem),
+        _ => return -EINVAL,
+    };
 
+    // request a unit of the semaphore
     let cur_tid = current_task().unwrap().inner_exclusive_access().res.as_ref().unwrap().tid;
-    while process_inner.semaphore_need[cur_tid].len() < (sem_id + 1) {
-        process_inner.semaphore_need[cur_tid].push(0);
-    }
-    // 请求加锁, 需求+1
+    ensure_column(&mut process_inner.semaphore_need[cur_tid], sem_id);
     process_inner.semaphore_need[cur_tid][sem_id] += 1;
 
     // deadlock detect
-    if process_inner.deadlock_detect {
-        let mut work = process_inner.semaphore_available.clone();
-        let mut finish = vec![false; process_inner.tasks.len()];
-
-        // 是否找到符合条件的线程
-        loop {
-            let mut is_found = false;
-            for tid in 0..process_inner.tasks.len() {
-                // 遍历所有资源, 查询是否满足需求
-                let mut valid = true;
-                for (i, j) in work.iter().enumerate() {
-                    while process_inner.semaphore_need[tid].len() < (i + 1) {
-                        process_inner.semaphore_need[tid].push(0);
-                    }
-                    if process_inner.semaphore_need[tid][i] > *j {
-                        valid = false;
-                    }
-                }
-
-                if !finish[tid] && valid {
-                    // 遍历所有资源, 更新work
-                    for (i, j) in work.iter_mut().enumerate() {
-                        while process_inner.semaphore_allocation[tid].len() < (i + 1) {
-                            process_inner.semaphore_allocation[tid].push(0);
-                        }
-                        *j += process_inner.semaphore_allocation[tid][i];
-                    }
-                    finish[tid] = true;
-                    is_found = true;
-                }
-            }
-            if !is_found {
-                // 出现死锁
-                if !finish.iter().all(|&value| value) {
-                    return -0xDEAD;
-                }
-                break;
-            }
-        }
-    }
-
-    // assert_ne!(process_inner.semaphore_available[sem_id], 0);
-    if process_inner.semaphore_available[sem_id] >= 1 {
-        process_inner.semaphore_available[sem_id] -= 1;
-        while process_inner.semaphore_allocation[cur_tid].len() < (sem_id + 1) {
-            process_inner.semaphore_allocation[cur_tid].push(0);
-        }
-        process_inner.semaphore_allocation[cur_tid][sem_id] += 1;
+    if process_inner.deadlock_detect && !process_inner.is_safe_state() {
         process_inner.semaphore_need[cur_tid][sem_id] -= 1;
+        return -0xDEAD;
     }
 
     drop(process_inner);
-    sem.down();
+    drop(process);
+    let acquired = sem.down(expire_ms);
+
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    if !acquired {
+        // withdraw the request
+        process_inner.semaphore_need[cur_tid][sem_id] -= 1;
+        drop(process_inner);
+        return if current_interrupted() {
+            -EINTR
+        } else {
+            -ETIMEDOUT
+        };
+    }
+    // the unit is granted
+    process_inner.semaphore_available[sem_id] -= 1;
+    ensure_column(&mut process_inner.semaphore_allocation[cur_tid], sem_id);
+    process_inner.semaphore_allocation[cur_tid][sem_id] += 1;
+    process_inner.semaphore_need[cur_tid][sem_id] -= 1;
     0
 }
 
@@ -372,13 +559,43 @@ pub fn sys_condvar_signal(condvar_id: usize) -> isize {
     );
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
+    let condvar = match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) => Arc::clone(condvar),
+        _ => return -EINVAL,
+    };
     drop(process_inner);
     condvar.signal();
     0
 }
 
+/// condvar broadcast syscall
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_condvar_broadcast",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let condvar = match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) => Arc::clone(condvar),
+        _ => return -EINVAL,
+    };
+    drop(process_inner);
+    condvar.broadcast();
+    0
+}
+
 /// condvar wait syscall
+///
+/// return -EPERM if the mutex is not held by the current thread. The mutex is
+/// locked again if a signal interrupts the wait, which returns -EINTR.
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     trace!(
         "kernel:pid[{}] tid[{}] sys_condvar_wait",
@@ -391,15 +608,462 @@ pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
             .unwrap()
             .tid
     );
+    condvar_wait(condvar_id, mutex_id, None)
+}
+
+/// condvar wait syscall giving up after `timeout`
+///
+/// The mutex is locked again in either case, return -ETIMEDOUT if the
+/// condvar is not signalled in time.
+pub fn sys_condvar_timedwait(
+    condvar_id: usize,
+    mutex_id: usize,
+    timeout: *const TimeSpec,
+) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_condvar_timedwait",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    match read_timeout(current_user_token(), timeout) {
+        Ok(expire_ms) => condvar_wait(condvar_id, mutex_id, Some(expire_ms)),
+        Err(errno) => errno,
+    }
+}
+
+/// Wait on a condvar, giving up at `expire_ms` if given, or when a signal
+/// interrupts the wait
+fn condvar_wait(condvar_id: usize, mutex_id: usize, expire_ms: Option<usize>) -> isize {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let mut process_inner = process.inner_exclusive_access();
+    let condvar = match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) => Arc::clone(condvar),
+        _ => return -EINVAL,
+    };
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return -EINVAL,
+    };
+    if !mutex.is_owner(&current_task().unwrap()) {
+        return -EPERM;
+    }
+    // the mutex is released while waiting, and requested again on wakeup
+    let cur_tid = current_task().unwrap().inner_exclusive_access().res.as_ref().unwrap().tid;
+    ensure_column(&mut process_inner.mutex_allocation[cur_tid], mutex_id);
+    ensure_column(&mut process_inner.mutex_need[cur_tid], mutex_id);
+    if process_inner.mutex_allocation[cur_tid][mutex_id] > 0 {
+        process_inner.mutex_allocation[cur_tid][mutex_id] -= 1;
+        process_inner.mutex_available[mutex_id] += 1;
+    }
+    process_inner.mutex_need[cur_tid][mutex_id] += 1;
+    drop(process_inner);
+    let signalled = condvar.wait(Arc::clone(&mutex), expire_ms);
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner.mutex_available[mutex_id] -= 1;
+    process_inner.mutex_allocation[cur_tid][mutex_id] += 1;
+    process_inner.mutex_need[cur_tid][mutex_id] -= 1;
+    if mutex.take_owner_dead() {
+        -EOWNERDEAD
+    } else if signalled {
+        0
+    } else if current_interrupted() {
+        -EINTR
+    } else {
+        -ETIMEDOUT
+    }
+}
+
+/// Forget the deadlock detection column of a destroyed resource
+fn clear_column(available: &mut [usize], allocation: &mut [Vec<usize>], id: usize) {
+    available[id] = 0;
+    for row in allocation.iter_mut() {
+        if let Some(count) = row.get_mut(id) {
+            *count = 0;
+        }
+    }
+}
+
+/// mutex destroy syscall
+///
+/// return -EBUSY if the mutex is held or waited for, either to be locked or
+/// locked again by a thread waiting on a condvar with it
+pub fn sys_mutex_destroy(mutex_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_mutex_destroy",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    // a thread waiting on a condvar still needs the mutex
+    let needed = process_inner
+        .mutex_need
+        .iter()
+        .any(|row| row.get(mutex_id).map_or(false, |count| *count > 0));
+    match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) if needed || !mutex.is_idle() => -EBUSY,
+        Some(Some(_)) => {
+            process_inner.mutex_list[mutex_id] = None;
+            let inner = &mut *process_inner;
+            clear_column(&mut inner.mutex_available, &mut inner.mutex_allocation, mutex_id);
+            0
+        }
+        _ => -EINVAL,
+    }
+}
+
+/// semaphore destroy syscall
+///
+/// return -EBUSY if any thread waits on the semaphore
+pub fn sys_semaphore_destroy(sem_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_semaphore_destroy",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) if !sem.is_idle() => -EBUSY,
+        Some(Some(_)) => {
+            process_inner.semaphore_list[sem_id] = None;
+            let inner = &mut *process_inner;
+            clear_column(
+                &mut inner.semaphore_available,
+                &mut inner.semaphore_allocation,
+                sem_id,
+            );
+            0
+        }
+        _ => -EINVAL,
+    }
+}
+
+/// condvar destroy syscall
+///
+/// return -EBUSY if any thread waits on the condvar
+pub fn sys_condvar_destroy(condvar_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_condvar_destroy",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) if !condvar.is_idle() => -EBUSY,
+        Some(Some(_)) => {
+            process_inner.condvar_list[condvar_id] = None;
+            0
+        }
+        _ => -EINVAL,
+    }
+}
+
+/// rwlock create syscall
+pub fn sys_rwlock_create() -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_rwlock_create",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let rwlock = Some(Arc::new(RwLock::new()));
+    let id = if let Some(id) = process_inner
+        .rwlock_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.rwlock_list[id] = rwlock;
+        id
+    } else {
+        process_inner.rwlock_list.push(rwlock);
+        process_inner.rwlock_list.len() - 1
+    };
+
+    // update rwlock_available, a reused id starts with a clean column
+    let available = &mut process_inner.rwlock_available;
+    while available.len() < (id + 1) {
+        available.push(0);
+    }
+    available[id] = RWLOCK_MAX_READERS;
+    let inner = &mut *process_inner;
+    for row in inner
+        .rwlock_allocation
+        .iter_mut()
+        .chain(inner.rwlock_need.iter_mut())
+    {
+        ensure_column(row, id);
+        row[id] = 0;
+    }
+    id as isize
+}
+
+/// Lock a rwlock for reading or writing, a reader takes one unit of it in
+/// the deadlock detection and a writer takes all units
+fn rwlock_lock(rwlock_id: usize, write: bool) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let rwlock = match process_inner.rwlock_list.get(rwlock_id) {
+        Some(Some(rwlock)) => Arc::clone(rwlock),
+        _ => return -EINVAL,
+    };
+    let units = if write { RWLOCK_MAX_READERS } else { 1 };
+
+    // request the units
+    let cur_tid = current_task().unwrap().inner_exclusive_access().res.as_ref().unwrap().tid;
+    ensure_column(&mut process_inner.rwlock_need[cur_tid], rwlock_id);
+    process_inner.rwlock_need[cur_tid][rwlock_id] += units;
+
+    // deadlock detect
+    if process_inner.deadlock_detect && !process_inner.is_safe_state() {
+        process_inner.rwlock_need[cur_tid][rwlock_id] -= units;
+        return -0xDEAD;
+    }
+
+    drop(process_inner);
+    drop(process);
+    if write {
+        rwlock.write_lock();
+    } else {
+        rwlock.read_lock();
+    }
+
+    // the units are granted
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner.rwlock_available[rwlock_id] -= units;
+    ensure_column(&mut process_inner.rwlock_allocation[cur_tid], rwlock_id);
+    process_inner.rwlock_allocation[cur_tid][rwlock_id] += units;
+    process_inner.rwlock_need[cur_tid][rwlock_id] -= units;
+    0
+}
+
+/// rwlock read lock syscall
+///
+/// return -0xDEAD if deadlock detection is enabled and the request is unsafe
+pub fn sys_rwlock_read_lock(rwlock_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_rwlock_read_lock",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    rwlock_lock(rwlock_id, false)
+}
+
+/// rwlock write lock syscall
+///
+/// return -0xDEAD if deadlock detection is enabled and the request is unsafe
+pub fn sys_rwlock_write_lock(rwlock_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_rwlock_write_lock",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    rwlock_lock(rwlock_id, true)
+}
+
+/// rwlock unlock syscall, for both readers and writers
+pub fn sys_rwlock_unlock(rwlock_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_rwlock_unlock",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let rwlock = match process_inner.rwlock_list.get(rwlock_id) {
+        Some(Some(rwlock)) => Arc::clone(rwlock),
+        _ => return -EINVAL,
+    };
+    if rwlock.is_idle() {
+        return -EINVAL;
+    }
+    let units = if rwlock.is_write_locked() {
+        RWLOCK_MAX_READERS
+    } else {
+        1
+    };
+
+    let cur_tid = current_task().unwrap().inner_exclusive_access().res.as_ref().unwrap().tid;
+    process_inner.rwlock_available[rwlock_id] += units;
+    ensure_column(&mut process_inner.rwlock_allocation[cur_tid], rwlock_id);
+    let allocation = &mut process_inner.rwlock_allocation[cur_tid][rwlock_id];
+    *allocation -= units.min(*allocation);
+
     drop(process_inner);
-    condvar.wait(mutex);
+    drop(process);
+    rwlock.unlock();
     0
 }
 
+/// rwlock destroy syscall
+///
+/// return -EBUSY if the rwlock is held or waited for
+pub fn sys_rwlock_destroy(rwlock_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_rwlock_destroy",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.rwlock_list.get(rwlock_id) {
+        Some(Some(rwlock)) if !rwlock.is_idle() => -EBUSY,
+        Some(Some(_)) => {
+            process_inner.rwlock_list[rwlock_id] = None;
+            let inner = &mut *process_inner;
+            clear_column(&mut inner.rwlock_available, &mut inner.rwlock_allocation, rwlock_id);
+            0
+        }
+        _ => -EINVAL,
+    }
+}
+
+/// barrier create syscall
+pub fn sys_barrier_create(count: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_barrier_create",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    if count == 0 {
+        return -EINVAL;
+    }
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let barrier = Some(Arc::new(Barrier::new(count)));
+    let id = if let Some(id) = process_inner
+        .barrier_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.barrier_list[id] = barrier;
+        id
+    } else {
+        process_inner.barrier_list.push(barrier);
+        process_inner.barrier_list.len() - 1
+    };
+    id as isize
+}
+
+/// barrier wait syscall
+///
+/// return 1 to the last thread to arrive, and 0 to the others
+pub fn sys_barrier_wait(barrier_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_barrier_wait",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let barrier = match process_inner.barrier_list.get(barrier_id) {
+        Some(Some(barrier)) => Arc::clone(barrier),
+        _ => return -EINVAL,
+    };
+    drop(process_inner);
+    barrier.wait() as isize
+}
+
+/// barrier destroy syscall
+///
+/// return -EBUSY if any thread is waiting at the barrier
+pub fn sys_barrier_destroy(barrier_id: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_barrier_destroy",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.barrier_list.get(barrier_id) {
+        Some(Some(barrier)) if !barrier.is_idle() => -EBUSY,
+        Some(Some(_)) => {
+            process_inner.barrier_list[barrier_id] = None;
+            0
+        }
+        _ => -EINVAL,
+    }
+}
+
 /// enable deadlock detection syscall
 ///
 /// YOUR JOB: Implement deadlock detection, but might not all in this syscall
@@ -1,6 +1,9 @@
 use crate::{
     mm::kernel_token,
-    task::{add_task, current_task, TaskControlBlock},
+    task::{
+        add_task, block_current_and_run_next, current_process, current_task, TaskControlBlock,
+        RLIMIT_NPROC,
+    },
     trap::{trap_handler, TrapContext},
 };
 use alloc::sync::Arc;
@@ -21,6 +24,14 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     );
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
+    // the number of threads is limited by RLIMIT_NPROC, and the stack of the
+    // new thread by RLIMIT_AS
+    let process_inner = process.inner_exclusive_access();
+    let thread_count = process_inner.tasks.iter().filter(|t| t.is_some()).count();
+    if thread_count >= process_inner.rlimits.cur(RLIMIT_NPROC) || !process_inner.user_res_fits() {
+        return -1;
+    }
+    drop(process_inner);
     // create a new thread
     let new_task = Arc::new(TaskControlBlock::new(
         Arc::clone(&process),
@@ -31,11 +42,16 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
             .ustack_base,
         true,
     ));
+    // the new thread inherits the signal mask of its creator
+    new_task.inner_exclusive_access().signal_mask = task.inner_exclusive_access().signal_mask;
     // add new task to scheduler
     add_task(Arc::clone(&new_task));
     let new_task_inner = new_task.inner_exclusive_access();
     let new_task_res = new_task_inner.res.as_ref().unwrap();
     let new_task_tid = new_task_res.tid;
+    // both of them need to access the PCB, so do it before borrowing it
+    let user_sp = new_task_res.user_sp();
+    let tls_base = new_task_res.tls_base();
     let mut process_inner = process.inner_exclusive_access();
 
     // // add new thread to current process
@@ -53,18 +69,30 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
         process_inner.mutex_need.push(Vec::new());
         process_inner.semaphore_allocation.push(Vec::new());
         process_inner.semaphore_need.push(Vec::new());
+        process_inner.rwlock_allocation.push(Vec::new());
+        process_inner.rwlock_need.push(Vec::new());
     }
     process_inner.tasks[new_task_tid] = Some(Arc::clone(&new_task));
+    // a reused tid starts with clean rows
+    process_inner.mutex_allocation[new_task_tid].clear();
+    process_inner.mutex_need[new_task_tid].clear();
+    process_inner.semaphore_allocation[new_task_tid].clear();
+    process_inner.semaphore_need[new_task_tid].clear();
+    process_inner.rwlock_allocation[new_task_tid].clear();
+    process_inner.rwlock_need[new_task_tid].clear();
 
     let new_task_trap_cx = new_task_inner.get_trap_cx();
     *new_task_trap_cx = TrapContext::app_init_context (
         entry,
-        new_task_res.ustack_top(),
+        user_sp,
         kernel_token(),
         new_task.kstack.get_top(),
         trap_handler as usize,
     );
     (*new_task_trap_cx).x[10] = arg;
+    if let Some(tls_base) = tls_base {
+        new_task_trap_cx.set_tp(tls_base);
+    }
     new_task_tid as isize
 }
 /// get current thread id syscall
@@ -91,9 +119,9 @@ pub fn sys_gettid() -> isize {
 
 /// wait for a thread to exit syscall
 ///
-/// thread does not exist, return -1
-/// thread has not exited yet, return -2
-/// otherwise, return thread's exit code
+/// thread does not exist, is detached or is already being joined, return -1
+/// thread has not exited yet, block until it exits
+/// otherwise, return thread's exit code and reclaim the thread
 pub fn sys_waittid(tid: usize) -> i32 {
     trace!(
         "kernel:pid[{}] tid[{}] sys_waittid",
@@ -108,28 +136,82 @@ pub fn sys_waittid(tid: usize) -> i32 {
     );
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
-    let task_inner = task.inner_exclusive_access();
-    let mut process_inner = process.inner_exclusive_access();
     // a thread cannot wait for itself
-    if task_inner.res.as_ref().unwrap().tid == tid {
+    if task.inner_exclusive_access().res.as_ref().unwrap().tid == tid {
         return -1;
     }
-    let mut exit_code: Option<i32> = None;
-    let waited_task = process_inner.tasks[tid].as_ref();
-    if let Some(waited_task) = waited_task {
-        if let Some(waited_exit_code) = waited_task.inner_exclusive_access().exit_code {
-            exit_code = Some(waited_exit_code);
+    loop {
+        let mut process_inner = process.inner_exclusive_access();
+        let waited_task = match process_inner.tasks.get(tid) {
+            Some(Some(waited_task)) => Arc::clone(waited_task),
+            // waited thread does not exist
+            _ => return -1,
+        };
+        let mut waited_inner = waited_task.inner_exclusive_access();
+        if waited_inner.detached {
+            return -1;
+        }
+        if let Some(exit_code) = waited_inner.exit_code {
+            drop(waited_inner);
+            // dealloc the exited thread, its tid is recycled when the last
+            // reference is dropped, which needs the PCB to be released first
+            process_inner.tasks[tid] = None;
+            drop(process_inner);
+            drop(waited_task);
+            return exit_code;
+        }
+        // only one thread may join a thread, after a spurious wakeup the
+        // joiner itself may still be queued
+        if waited_inner
+            .join_queue
+            .iter()
+            .any(|joiner| !Arc::ptr_eq(joiner, &task))
+        {
+            return -1;
         }
-    } else {
-        // waited thread does not exist
+        if waited_inner.join_queue.is_empty() {
+            waited_inner.join_queue.push_back(Arc::clone(&task));
+        }
+        drop(waited_inner);
+        drop(process_inner);
+        drop(waited_task);
+        block_current_and_run_next();
+    }
+}
+
+/// detach a thread syscall
+///
+/// thread does not exist, is already detached or is being joined, return -1
+/// an exited thread is reclaimed at once, otherwise it is reclaimed on exit
+pub fn sys_thread_detach(tid: usize) -> isize {
+    trace!(
+        "kernel:pid[{}] tid[{}] sys_thread_detach",
+        current_task().unwrap().process.upgrade().unwrap().getpid(),
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .tid
+    );
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let detached_task = match process_inner.tasks.get(tid) {
+        Some(Some(detached_task)) => Arc::clone(detached_task),
+        _ => return -1,
+    };
+    let mut detached_inner = detached_task.inner_exclusive_access();
+    if detached_inner.detached || !detached_inner.join_queue.is_empty() {
         return -1;
     }
-    if let Some(exit_code) = exit_code {
-        // dealloc the exited thread
+    if detached_inner.exit_code.is_some() {
+        drop(detached_inner);
         process_inner.tasks[tid] = None;
-        exit_code
-    } else {
-        // waited thread has not exited
-        -2
+        drop(process_inner);
+        drop(detached_task);
+        return 0;
     }
+    detached_inner.detached = true;
+    0
 }
@@ -0,0 +1,59 @@
+//! Signal actions of a process and the signal frame pushed on the user stack
+
+use super::SignalFlags;
+
+/// The largest signal number
+pub const MAX_SIG: usize = 31;
+/// Take the default action of the signal
+pub const SIG_DFL: usize = 0;
+/// Ignore the signal
+pub const SIG_IGN: usize = 1;
+
+/// Action taken on a signal, passed to and from `sys_sigaction`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalAction {
+    /// SIG_DFL, SIG_IGN or the address of the user handler
+    pub handler: usize,
+    /// signals blocked in addition while the handler runs
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
+
+/// The signal action table of a process, indexed by signal number
+#[derive(Clone)]
+pub struct SignalActions {
+    /// actions of signal 0..=MAX_SIG, the entry of 0 is unused
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+}
+
+/// The user context saved on the user stack when a handler is called, and
+/// restored by `sys_sigreturn`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalFrame {
+    /// general-purpose registers of the interrupted context
+    pub x: [usize; 32],
+    /// pc of the interrupted context
+    pub sepc: usize,
+    /// signal mask of the thread before the handler is called
+    pub mask: u32,
+    /// address of the previous frame, 0 if this is the outermost one
+    pub prev: usize,
+}
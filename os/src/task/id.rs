@@ -2,7 +2,9 @@
 
 use super::ProcessControlBlock;
 use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
-use crate::mm::{MapPermission, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::mm::{
+    translated_byte_buffer, MapPermission, PhysPageNum, TlsTemplate, VirtAddr, KERNEL_SPACE,
+};
 use crate::sync::UPSafeCell;
 use alloc::{
     sync::{Arc, Weak},
@@ -132,6 +134,8 @@ pub struct TaskUserRes {
     pub tid: usize,
     /// user stack base
     pub ustack_base: usize,
+    /// size of the user stack, limited by RLIMIT_STACK
+    pub ustack_size: usize,
     /// process belongs to
     pub process: Weak<ProcessControlBlock>,
 }
@@ -139,10 +143,15 @@ pub struct TaskUserRes {
 fn trap_cx_bottom_from_tid(tid: usize) -> usize {
     TRAP_CONTEXT_BASE - tid * PAGE_SIZE
 }
-/// Return the bottom addr (high addr) of the user stack for a task
+/// Return the bottom addr (low addr) of the space reserved for the user stack of a task
 fn ustack_bottom_from_tid(ustack_base: usize, tid: usize) -> usize {
     ustack_base + tid * (PAGE_SIZE + USER_STACK_SIZE)
 }
+/// Return the base addr of the tls block, which is carved from the top of the user stack
+fn tls_base_from_ustack_top(ustack_top: usize, tls: &TlsTemplate) -> usize {
+    let align = tls.align.max(core::mem::size_of::<usize>());
+    (ustack_top - tls.mem_size) & !(align - 1)
+}
 
 impl TaskUserRes {
     /// Create a new TaskUserRes (Task User Resource)
@@ -151,10 +160,14 @@ impl TaskUserRes {
         ustack_base: usize,
         alloc_user_res: bool,
     ) -> Self {
-        let tid = process.inner_exclusive_access().alloc_tid();
+        let mut process_inner = process.inner_exclusive_access();
+        let tid = process_inner.alloc_tid();
+        let ustack_size = process_inner.ustack_size();
+        drop(process_inner);
         let task_user_res = Self {
             tid,
             ustack_base,
+            ustack_size,
             process: Arc::downgrade(&process),
         };
         if alloc_user_res {
@@ -167,13 +180,28 @@ impl TaskUserRes {
         let process = self.process.upgrade().unwrap();
         let mut process_inner = process.inner_exclusive_access();
         // alloc user stack
-        let ustack_bottom = ustack_bottom_from_tid(self.ustack_base, self.tid);
-        let ustack_top = ustack_bottom + USER_STACK_SIZE;
+        let ustack_top = self.ustack_top();
+        let ustack_bottom = ustack_top - self.ustack_size;
         process_inner.memory_set.insert_framed_area(
             ustack_bottom.into(),
             ustack_top.into(),
             MapPermission::R | MapPermission::W | MapPermission::U,
         );
+        // copy the tls image into the tls block of this task,
+        // the rest of the block(.tbss) has been zeroed by the frame allocator
+        if let Some(tls) = process_inner.memory_set.tls {
+            let token = process_inner.memory_set.token();
+            let mut image: Vec<u8> = Vec::new();
+            for slice in translated_byte_buffer(token, tls.start_va as *const u8, tls.file_size) {
+                image.extend_from_slice(slice);
+            }
+            let tls_base = tls_base_from_ustack_top(ustack_top, &tls);
+            let mut copied = 0;
+            for slice in translated_byte_buffer(token, tls_base as *const u8, tls.file_size) {
+                slice.copy_from_slice(&image[copied..copied + slice.len()]);
+                copied += slice.len();
+            }
+        }
         // alloc trap_cx
         let trap_cx_bottom = trap_cx_bottom_from_tid(self.tid);
         let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
@@ -184,12 +212,15 @@ impl TaskUserRes {
         );
     }
     /// Deallocate user resource for a task
-    fn dealloc_user_res(&self) {
+    ///
+    /// It is safe to call this more than once, e.g. when a thread releases its
+    /// user stack at exit while its tid is kept until it is joined.
+    pub fn dealloc_user_res(&self) {
         // dealloc tid
         let process = self.process.upgrade().unwrap();
         let mut process_inner = process.inner_exclusive_access();
         // dealloc ustack manually
-        let ustack_bottom_va: VirtAddr = ustack_bottom_from_tid(self.ustack_base, self.tid).into();
+        let ustack_bottom_va: VirtAddr = (self.ustack_top() - self.ustack_size).into();
         process_inner
             .memory_set
             .remove_area_with_start_vpn(ustack_bottom_va.into());
@@ -239,6 +270,19 @@ impl TaskUserRes {
     pub fn ustack_top(&self) -> usize {
         ustack_bottom_from_tid(self.ustack_base, self.tid) + USER_STACK_SIZE
     }
+    /// the base addr of the tls block for a task, which should be put in `tp`
+    pub fn tls_base(&self) -> Option<usize> {
+        let process = self.process.upgrade().unwrap();
+        let process_inner = process.inner_exclusive_access();
+        process_inner
+            .memory_set
+            .tls
+            .map(|tls| tls_base_from_ustack_top(self.ustack_top(), &tls))
+    }
+    /// the initial user stack pointer for a task, right below its tls block
+    pub fn user_sp(&self) -> usize {
+        self.tls_base().unwrap_or(self.ustack_top()) & !0xf
+    }
 }
 
 impl Drop for TaskUserRes {
@@ -0,0 +1,140 @@
+//! Interval timers of a process
+//!
+//! The real timer counts wall time and is driven by the `TIMERS` heap, the
+//! virtual and profiling timers count the cpu time of the process and are
+//! checked on every timer tick.
+
+use super::{send_signal, ProcessControlBlock, SignalFlags};
+use crate::timer::{add_real_timer, get_time_us, remove_real_timer};
+use alloc::sync::Arc;
+
+/// Wall time, posts SIGALRM
+pub const ITIMER_REAL: usize = 0;
+/// User time of the process, posts SIGVTALRM
+pub const ITIMER_VIRTUAL: usize = 1;
+/// User and kernel time of the process, posts SIGPROF
+pub const ITIMER_PROF: usize = 2;
+/// The number of interval timers
+pub const ITIMER_COUNT: usize = 3;
+
+/// An interval timer
+#[derive(Copy, Clone, Default)]
+pub struct IntervalTimer {
+    /// the period in microseconds, 0 for a one-shot timer
+    pub interval_us: usize,
+    /// when the timer expires on its clock in microseconds, 0 if disarmed
+    pub expire_us: usize,
+}
+
+impl IntervalTimer {
+    /// Re-arm the timer after it expired at `now_us`, or disarm a one-shot one
+    fn rearm(&mut self, now_us: usize) {
+        if self.interval_us == 0 {
+            self.expire_us = 0;
+        } else {
+            // skip the periods missed, a signal is posted once anyway
+            self.expire_us = (self.expire_us + self.interval_us).max(now_us + 1);
+        }
+    }
+}
+
+/// The clock of an interval timer of a process, in microseconds
+fn itimer_clock_us(process: &Arc<ProcessControlBlock>, which: usize) -> usize {
+    if which == ITIMER_REAL {
+        return get_time_us();
+    }
+    let (user_time_us, kernel_time_us) = process.inner_exclusive_access().cpu_time_us();
+    if which == ITIMER_VIRTUAL {
+        user_time_us
+    } else {
+        user_time_us + kernel_time_us
+    }
+}
+
+/// Get the period and the remaining time of an interval timer of a process,
+/// in microseconds
+pub fn get_itimer(process: &Arc<ProcessControlBlock>, which: usize) -> (usize, usize) {
+    let now_us = itimer_clock_us(process, which);
+    let timer = process.inner_exclusive_access().itimers[which];
+    let remaining_us = if timer.expire_us == 0 {
+        0
+    } else {
+        // an expired timer not checked yet is about to fire
+        (timer.expire_us - now_us.min(timer.expire_us)).max(1)
+    };
+    (timer.interval_us, remaining_us)
+}
+
+/// Arm an interval timer of a process to expire after `value_us` and then
+/// every `interval_us`, or disarm it if `value_us` is 0
+///
+/// Return the period and the remaining time of the old setting.
+pub fn set_itimer(
+    process: &Arc<ProcessControlBlock>,
+    which: usize,
+    interval_us: usize,
+    value_us: usize,
+) -> (usize, usize) {
+    let old = get_itimer(process, which);
+    let now_us = itimer_clock_us(process, which);
+    let timer = IntervalTimer {
+        interval_us,
+        expire_us: if value_us == 0 { 0 } else { now_us + value_us },
+    };
+    process.inner_exclusive_access().itimers[which] = timer;
+    if which == ITIMER_REAL {
+        remove_real_timer(process);
+        if timer.expire_us != 0 {
+            add_real_timer((timer.expire_us + 999) / 1000, Arc::downgrade(process));
+        }
+    }
+    old
+}
+
+/// The real timer of a process expires, post SIGALRM and re-arm a periodic one
+pub fn expire_real_timer(process: &Arc<ProcessControlBlock>) {
+    let mut process_inner = process.inner_exclusive_access();
+    if process_inner.is_zombie {
+        return;
+    }
+    let timer = &mut process_inner.itimers[ITIMER_REAL];
+    if timer.expire_us == 0 {
+        return;
+    }
+    timer.rearm(get_time_us());
+    let expire_us = timer.expire_us;
+    drop(process_inner);
+    if expire_us != 0 {
+        add_real_timer((expire_us + 999) / 1000, Arc::downgrade(process));
+    }
+    send_signal(process, SignalFlags::SIGALRM);
+}
+
+/// Post SIGVTALRM and SIGPROF to a process whose virtual and profiling
+/// timers expire at the given cpu time
+pub fn check_cpu_itimers(
+    process: &Arc<ProcessControlBlock>,
+    user_time_us: usize,
+    kernel_time_us: usize,
+) {
+    let mut process_inner = process.inner_exclusive_access();
+    let mut signals = SignalFlags::empty();
+    for (which, now_us, signal) in [
+        (ITIMER_VIRTUAL, user_time_us, SignalFlags::SIGVTALRM),
+        (
+            ITIMER_PROF,
+            user_time_us + kernel_time_us,
+            SignalFlags::SIGPROF,
+        ),
+    ] {
+        let timer = &mut process_inner.itimers[which];
+        if timer.expire_us != 0 && now_us >= timer.expire_us {
+            timer.rearm(now_us);
+            signals |= signal;
+        }
+    }
+    drop(process_inner);
+    if !signals.is_empty() {
+        send_signal(process, signals);
+    }
+}
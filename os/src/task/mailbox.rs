@@ -0,0 +1,40 @@
+//! Mailbox of a process
+//!
+//! A bounded queue of short messages any process may send to by pid.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// The most messages a mailbox holds
+pub const MAIL_MAX_MESSAGES: usize = 16;
+/// The longest message in bytes, longer ones are truncated
+pub const MAIL_MAX_LEN: usize = 256;
+
+/// Mailbox structure
+#[derive(Default)]
+pub struct Mailbox {
+    messages: VecDeque<Vec<u8>>,
+}
+
+impl Mailbox {
+    /// Is there no message?
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Is there no room for another message?
+    pub fn is_full(&self) -> bool {
+        self.messages.len() == MAIL_MAX_MESSAGES
+    }
+
+    /// Queue a message, the caller checks there is room for it
+    pub fn push(&mut self, message: Vec<u8>) {
+        assert!(!self.is_full() && message.len() <= MAIL_MAX_LEN);
+        self.messages.push_back(message);
+    }
+
+    /// Take the oldest message
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.messages.pop_front()
+    }
+}
@@ -11,12 +11,12 @@ use lazy_static::*;
 ///A array of `TaskControlBlock` that is thread-safe
 pub struct TaskManager {
     ready_queue: VecDeque<Arc<TaskControlBlock>>,
-    
+
     /// The stopping task, leave a reference so that the kernel stack will not be recycled when switching tasks
     stop_task: Option<Arc<TaskControlBlock>>,
 }
 
-/// A simple FIFO scheduler.
+/// A priority scheduler, FIFO among tasks of the same priority.
 impl TaskManager {
     ///Creat an empty TaskManager
     pub fn new() -> Self {
@@ -29,9 +29,21 @@ impl TaskManager {
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
         self.ready_queue.push_back(task);
     }
-    /// Take a process out of the ready queue
+    /// Take the first process of the highest priority out of the ready queue
+    ///
+    /// The priority is the effective one, so the owner of a blocking mutex
+    /// runs ahead of the tasks it keeps a waiter of higher priority from.
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        let mut next: Option<(usize, usize)> = None;
+        for (id, task) in self.ready_queue.iter().enumerate() {
+            let priority = task.inner_exclusive_access().effective_priority;
+            match next {
+                Some((_, max)) if priority <= max => {}
+                _ => next = Some((id, priority)),
+            }
+        }
+        let (id, _) = next?;
+        self.ready_queue.remove(id)
     }
     pub fn remove(&mut self, task: Arc<TaskControlBlock>) {
         if let Some((id, _)) = self
@@ -69,9 +81,17 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
 }
 
 /// Wake up a task
+///
+/// Only a blocked task is woken up, so a task woken up by several sources,
+/// e.g. a wait queue and a timeout, is added to the ready queue only once.
+/// A task gone with its process is left in a wait queue of another process
+/// it blocked on, and is never woken up either.
 pub fn wakeup_task(task: Arc<TaskControlBlock>) {
     trace!("kernel: TaskManager::wakeup_task");
     let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked || task_inner.res.is_none() {
+        return;
+    }
     task_inner.task_status = TaskStatus::Ready;
     drop(task_inner);
     add_task(task);
@@ -100,6 +120,11 @@ pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
     map.get(&pid).map(Arc::clone)
 }
 
+/// The number of live processes, zombies are not counted
+pub fn process_count() -> usize {
+    PID2PCB.exclusive_access().len()
+}
+
 /// Insert item(pid, pcb) into PID2PCB map (called by do_fork AND ProcessControlBlock::new)
 pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
     PID2PCB.exclusive_access().insert(pid, process);
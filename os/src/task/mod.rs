@@ -9,11 +9,15 @@
 //! Be careful when you see [`__switch`]. Control flow around this function
 //! might not be what you expect.
 
+mod action;
 mod context;
 mod id;
+mod itimer;
+mod mailbox;
 mod manager;
 mod process;
 mod processor;
+mod rlimit;
 mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
@@ -21,22 +25,37 @@ mod task;
 
 use self::id::TaskUserRes;
 use crate::fs::{open_file, OpenFlags};
+use crate::mm::{copy_to_user, VirtAddr};
+use crate::sync::futex_remove_task;
 use crate::task::manager::add_stopping_task;
-use crate::timer::remove_timer;
+use crate::timer::{remove_real_timer, remove_timer};
 use alloc::{sync::Arc, vec::Vec};
 use lazy_static::*;
 use manager::fetch_task;
-use process::ProcessControlBlock;
+use process::ProcessControlBlockInner;
 use switch::__switch;
 
+pub use action::{SignalAction, SignalActions, SignalFrame, MAX_SIG, SIG_DFL, SIG_IGN};
 pub use context::TaskContext;
 pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle, IDLE_PID};
-pub use manager::{add_task, pid2process, remove_from_pid2process, remove_task, wakeup_task};
+pub use itimer::{
+    expire_real_timer, get_itimer, set_itimer, IntervalTimer, ITIMER_COUNT, ITIMER_PROF,
+    ITIMER_REAL, ITIMER_VIRTUAL,
+};
+pub use mailbox::{Mailbox, MAIL_MAX_LEN};
+pub use manager::{
+    add_task, pid2process, process_count, remove_from_pid2process, remove_task, wakeup_task,
+};
+pub use process::ProcessControlBlock;
 pub use processor::{
-    current_kstack_top, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, run_tasks, schedule, take_current_task,
+    current_charge_kernel_time, current_charge_user_time, current_kstack_top, current_process,
+    current_task, current_trap_cx, current_trap_cx_user_va, current_user_token, run_tasks,
+    schedule, take_current_task,
 };
-pub use signal::SignalFlags;
+pub use rlimit::{
+    RLimit, ResourceLimits, RLIMIT_AS, RLIMIT_CPU, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_STACK,
+};
+pub use signal::{SignalDefault, SignalFlags};
 pub use task::{TaskControlBlock, TaskStatus};
 
 /// Make current task suspended and switch to the next task
@@ -47,6 +66,7 @@ pub fn suspend_current_and_run_next() {
     // ---- access current TCB exclusively
     let mut task_inner = task.inner_exclusive_access();
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.charge_kernel_time();
     // Change status to Ready
     task_inner.task_status = TaskStatus::Ready;
     drop(task_inner);
@@ -63,13 +83,89 @@ pub fn block_current_and_run_next() {
     let task = take_current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.charge_kernel_time();
     task_inner.task_status = TaskStatus::Blocked;
     drop(task_inner);
     schedule(task_cx_ptr);
 }
 
+/// Make current task blocked like `block_current_and_run_next`, but a signal
+/// that interrupts a syscall wakes it up as well
+///
+/// Return false if such a signal is pending, before blocking or after being
+/// woken up. The caller then leaves the wait queue it has joined, and fails
+/// with EINTR unless it has got what it waits for.
+pub fn block_current_interruptible() -> bool {
+    if current_interrupted() {
+        return false;
+    }
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().interruptible = true;
+    block_current_and_run_next();
+    task.inner_exclusive_access().interruptible = false;
+    !current_interrupted()
+}
+
+/// Is a signal pending which interrupts a blocking syscall of the current
+/// thread? It is one not blocked by the thread, and either caught by a
+/// handler or killing the process.
+pub fn current_interrupted() -> bool {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let process_inner = process.inner_exclusive_access();
+    let task_inner = task.inner_exclusive_access();
+    interrupts(&process_inner, task_inner.signal_mask)
+}
+
+/// Does a pending signal of the process interrupt a thread with `mask`?
+fn interrupts(process_inner: &ProcessControlBlockInner, mask: SignalFlags) -> bool {
+    let pending = process_inner.signals - (mask - SignalFlags::UNCATCHABLE);
+    (1..=MAX_SIG).any(|signum| {
+        let flag = SignalFlags::from_bits_truncate(1 << signum);
+        if !pending.contains(flag) {
+            return false;
+        }
+        if SignalFlags::UNCATCHABLE.contains(flag) {
+            return flag.check_error().is_some();
+        }
+        match process_inner.signal_actions.table[signum].handler {
+            SIG_IGN => false,
+            SIG_DFL => flag.check_error().is_some(),
+            _ => true,
+        }
+    })
+}
+
 use crate::board::QEMUExit;
 
+/// Release the mutexes held by a thread exiting, the next owners get
+/// EOWNERDEAD when they lock them
+fn release_mutexes_of_exited(
+    process: &Arc<ProcessControlBlock>,
+    task: &Arc<TaskControlBlock>,
+    tid: usize,
+) {
+    let mutexes: Vec<_> = process
+        .inner_exclusive_access()
+        .mutex_list
+        .iter()
+        .enumerate()
+        .filter_map(|(id, mutex)| mutex.as_ref().map(|mutex| (id, Arc::clone(mutex))))
+        .collect();
+    for (id, mutex) in mutexes {
+        if !mutex.is_owner(task) {
+            continue;
+        }
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.mutex_available[id] += 1;
+        if let Some(count) = process_inner.mutex_allocation[tid].get_mut(id) {
+            *count = 0;
+        }
+        drop(process_inner);
+        mutex.release_dead_owner();
+    }
+}
+
 /// Exit the current 'Running' task and run the next task in task list.
 pub fn exit_current_and_run_next(exit_code: i32) {
     trace!(
@@ -83,14 +179,45 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     let tid = task_inner.res.as_ref().unwrap().tid;
     // record exit code
     task_inner.exit_code = Some(exit_code);
-    task_inner.res = None;
-    // here we do not remove the thread since we are still using the kstack
+    task_inner.charge_kernel_time();
+    if tid != 0 {
+        // the cpu time of an exited thread is kept by the process,
+        // the main thread is accounted below with the other threads
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.user_time_us += core::mem::take(&mut task_inner.user_time_us);
+        process_inner.kernel_time_us += core::mem::take(&mut task_inner.kernel_time_us);
+        // an exited thread is not waiting for any resource
+        process_inner.mutex_need[tid].fill(0);
+        process_inner.semaphore_need[tid].fill(0);
+        process_inner.rwlock_need[tid].fill(0);
+    }
+    let detached = task_inner.detached;
+    if tid == 0 || detached {
+        task_inner.res = None;
+    } else {
+        // release ustack and trap_cx at once, but keep the tid reserved until
+        // the thread is joined, so that its slot and exit code are not reused
+        task_inner.res.as_ref().unwrap().dealloc_user_res();
+    }
+    // wake up the threads joining this one
+    let joiners: Vec<_> = task_inner.join_queue.drain(..).collect();
+    // here we do not remove a joinable thread since we are still using the kstack
     // it will be deallocated when sys_waittid is called
     drop(task_inner);
+    if tid != 0 {
+        release_mutexes_of_exited(&process, &task, tid);
+    }
+    for joiner in joiners {
+        wakeup_task(joiner);
+    }
 
     // Move the task to stop-wait status, to avoid kernel stack from being freed
     if tid == 0 {
         add_stopping_task(task);
+    } else if detached {
+        // nobody is going to join a detached thread, remove it from the process
+        process.inner_exclusive_access().tasks[tid] = None;
+        add_stopping_task(task);
     } else {
         drop(task);
     }
@@ -112,25 +239,43 @@ pub fn exit_current_and_run_next(exit_code: i32) {
             }
         }
         remove_from_pid2process(pid);
+        remove_real_timer(&process);
         let mut process_inner = process.inner_exclusive_access();
         // mark this process as a zombie process
         process_inner.is_zombie = true;
         // record exit code of main process
         process_inner.exit_code = exit_code;
 
+        // threads of init process waiting for the orphans, and of the parent
+        // waiting for this process
+        let mut waiters = Vec::new();
         {
             // move all child processes under init process
             let mut initproc_inner = INITPROC.inner_exclusive_access();
-            for child in process_inner.children.iter() {
+            for child in process_inner.children.drain(..) {
                 child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
-                initproc_inner.children.push(child.clone());
+                initproc_inner.children.push(child);
+            }
+            // some of the orphans may be zombies already, let init process reap them
+            waiters.extend(initproc_inner.wait_queue.drain(..));
+        }
+        if let Some(parent) = process_inner.parent.as_ref().and_then(|p| p.upgrade()) {
+            if !Arc::ptr_eq(&parent, &INITPROC) {
+                waiters.extend(parent.inner_exclusive_access().wait_queue.drain(..));
             }
+            send_signal(&parent, SignalFlags::SIGCHLD);
         }
+        // nobody of this process is going to wait any more
+        process_inner.wait_queue.clear();
+        process_inner.stopped_tasks.clear();
+        process_inner.signalfd_waiters.clear();
 
         // deallocate user res (including tid/trap_cx/ustack) of all threads
         // it has to be done before we dealloc the whole memory_set
         // otherwise they will be deallocated twice
         let mut recycle_res = Vec::<TaskUserRes>::new();
+        let mut user_time_us = 0;
+        let mut kernel_time_us = 0;
         for task in process_inner.tasks.iter().filter(|t| t.is_some()) {
             let task = task.as_ref().unwrap();
             // if other tasks are Ready in TaskManager or waiting for a timer to be
@@ -145,7 +290,13 @@ pub fn exit_current_and_run_next(exit_code: i32) {
             if let Some(res) = task_inner.res.take() {
                 recycle_res.push(res);
             }
+            user_time_us += core::mem::take(&mut task_inner.user_time_us);
+            kernel_time_us += core::mem::take(&mut task_inner.kernel_time_us);
+            // joiners are torn down together with the process
+            task_inner.join_queue.clear();
         }
+        process_inner.user_time_us += user_time_us;
+        process_inner.kernel_time_us += kernel_time_us;
         // dealloc_tid and dealloc_user_res require access to PCB inner, so we
         // need to collect those user res first, then release process_inner
         // for now to avoid deadlock/double borrow problem.
@@ -153,13 +304,33 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         recycle_res.clear();
 
         let mut process_inner = process.inner_exclusive_access();
-        process_inner.children.clear();
         // deallocate other data in user space i.e. program code/data section
         process_inner.memory_set.recycle_data_pages();
-        // drop file descriptors
-        process_inner.fd_table.clear();
+        // drop file descriptors after the PCB is released, unregistering the
+        // threads from a file may look into the PCB
+        let files = core::mem::take(&mut process_inner.fd_table);
+        // drop sync objects, together with the threads blocked on them, so that
+        // their kernel stacks are not held until the process is reaped
+        process_inner.mutex_list.clear();
+        process_inner.semaphore_list.clear();
+        process_inner.condvar_list.clear();
+        process_inner.rwlock_list.clear();
+        process_inner.barrier_list.clear();
         // remove all tasks
-        process_inner.tasks.clear();
+        let tasks = core::mem::take(&mut process_inner.tasks);
+        drop(process_inner);
+        // the threads blocked on files shared with other processes must not be
+        // woken up once they are gone
+        for file in files.iter().flatten() {
+            for task in tasks.iter().flatten() {
+                file.unregister_waiter(task);
+            }
+        }
+        drop(files);
+        drop(tasks);
+        for waiter in waiters {
+            wakeup_task(waiter);
+        }
     }
     drop(process);
     // we do not have to save task context
@@ -176,6 +347,7 @@ lazy_static! {
         let inode = open_file("ch8b_initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
         ProcessControlBlock::new(v.as_slice())
+            .expect("the tls block of ch8b_initproc leaves no user stack")
     };
 }
 
@@ -184,18 +356,183 @@ pub fn add_initproc() {
     let _initproc = INITPROC.clone();
 }
 
-/// Check if the current task has any signal to handle
-pub fn check_signals_of_current() -> Option<(i32, &'static str)> {
+/// Handle the pending signals of the current thread before it returns to
+/// user space
+///
+/// Ignored signals are discarded, a stop signal parks the threads of the
+/// process until it is continued, and at most one signal with a user handler
+/// is delivered each time. Return the error of a signal that kills the process.
+///
+/// A temporary mask saved by an interrupted wait stays in effect while the
+/// signals are handled, the handler frame then holds the saved mask for
+/// sigreturn, otherwise it is restored at once.
+pub fn handle_signals_of_current() -> Option<(i32, &'static str)> {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let mut process_inner = process.inner_exclusive_access();
+    if process_inner.stopped {
+        // wait for SIGCONT or SIGKILL, then look at the signals again
+        process_inner.stopped_tasks.push(Arc::clone(&task));
+        drop(process_inner);
+        drop(process);
+        drop(task);
+        block_current_and_run_next();
+        return handle_signals_of_current();
+    }
+    let mut task_inner = task.inner_exclusive_access();
+    for signum in 1..=MAX_SIG {
+        let flag = SignalFlags::from_bits_truncate(1 << signum);
+        if !process_inner.signals.contains(flag)
+            || (task_inner.signal_mask - SignalFlags::UNCATCHABLE).contains(flag)
+        {
+            continue;
+        }
+        let action = process_inner.signal_actions.table[signum];
+        let handler = if SignalFlags::UNCATCHABLE.contains(flag) {
+            SIG_DFL
+        } else {
+            action.handler
+        };
+        match handler {
+            SIG_DFL => match flag.default_action() {
+                SignalDefault::Terminate | SignalDefault::CoreDump => return flag.check_error(),
+                SignalDefault::Stop => {
+                    process_inner.signals.remove(flag);
+                    drop(task_inner);
+                    stop_process(&mut process_inner, &task);
+                    drop(process_inner);
+                    return handle_signals_of_current();
+                }
+                SignalDefault::Continue | SignalDefault::Ignore => {
+                    process_inner.signals.remove(flag)
+                }
+            },
+            SIG_IGN => process_inner.signals.remove(flag),
+            handler => {
+                process_inner.signals.remove(flag);
+                // save the user context on the user stack
+                let trap_cx = task_inner.get_trap_cx();
+                let frame = SignalFrame {
+                    x: trap_cx.x,
+                    sepc: trap_cx.sepc,
+                    mask: task_inner
+                        .saved_signal_mask
+                        .take()
+                        .unwrap_or(task_inner.signal_mask)
+                        .bits(),
+                    prev: task_inner.sigframe,
+                };
+                let frame_size = core::mem::size_of::<SignalFrame>();
+                let frame_va = (trap_cx.x[2] - frame_size) & !0xf;
+                let start_vpn = VirtAddr::from(frame_va).floor();
+                let end_vpn = VirtAddr::from(frame_va + frame_size).ceil();
+                if !process_inner.memory_set.is_all_mapped(start_vpn, end_vpn) {
+                    // no room for the frame, the user stack overflows
+                    return SignalFlags::SIGSEGV.check_error();
+                }
+                copy_to_user(
+                    process_inner.get_user_token(),
+                    frame_va as *mut SignalFrame,
+                    &frame,
+                );
+                // block the signal itself and the mask of the action while
+                // the handler runs, sigreturn restores the old mask
+                task_inner.signal_mask |= flag | action.mask;
+                task_inner.sigframe = frame_va;
+                trap_cx.set_sp(frame_va);
+                trap_cx.sepc = handler;
+                trap_cx.x[10] = signum;
+                return None;
+            }
+        }
+    }
+    if let Some(mask) = task_inner.saved_signal_mask.take() {
+        task_inner.signal_mask = mask;
+    }
+    None
+}
+
+/// Check the cpu time of the current process on every timer tick: send
+/// SIGXCPU once it has used up the cpu time allowed by RLIMIT_CPU, and expire
+/// its virtual and profiling interval timers
+pub fn check_cpu_timers_of_current() {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    process_inner.signals.check_error()
+    let mut process_inner = process.inner_exclusive_access();
+    let (user_time_us, kernel_time_us) = process_inner.cpu_time_us();
+    if (user_time_us + kernel_time_us) / 1_000_000 >= process_inner.rlimits.cur(RLIMIT_CPU) {
+        process_inner.signals |= SignalFlags::SIGXCPU;
+    }
+    drop(process_inner);
+    itimer::check_cpu_itimers(&process, user_time_us, kernel_time_us);
 }
 
 /// Add signal to the current task
 pub fn current_add_signal(signal: SignalFlags) {
-    let process = current_process();
+    send_signal(&current_process(), signal);
+}
+
+/// Post signals to a process
+///
+/// SIGCONT and SIGKILL continue a stopped process at once. SIGCONT discards
+/// the pending stop signals, and a stop signal discards a pending SIGCONT.
+/// The threads in an interruptible wait are woken up if a signal interrupts
+/// them.
+pub fn send_signal(process: &Arc<ProcessControlBlock>, signal: SignalFlags) {
     let mut process_inner = process.inner_exclusive_access();
+    if signal.contains(SignalFlags::SIGCONT) {
+        process_inner.signals.remove(SignalFlags::STOP_SIGNALS);
+    }
+    if signal.intersects(SignalFlags::STOP_SIGNALS) {
+        process_inner.signals.remove(SignalFlags::SIGCONT);
+    }
     process_inner.signals |= signal;
+    for task in process_inner.signalfd_waiters.drain(..) {
+        wakeup_task(task);
+    }
+    if process_inner.stopped && signal.intersects(SignalFlags::SIGCONT | SignalFlags::SIGKILL) {
+        process_inner.stopped = false;
+        for task in process_inner.stopped_tasks.drain(..) {
+            wakeup_task(task);
+        }
+    }
+    let interrupted: Vec<_> = process_inner
+        .tasks
+        .iter()
+        .flatten()
+        .filter(|task| {
+            let task_inner = task.inner_exclusive_access();
+            task_inner.interruptible
+                && task_inner.task_status == TaskStatus::Blocked
+                && interrupts(&process_inner, task_inner.signal_mask)
+        })
+        .cloned()
+        .collect();
+    drop(process_inner);
+    for task in interrupted {
+        wakeup_task(task);
+    }
+}
+
+/// Stop a process on behalf of its running thread `current`, the other
+/// threads ready to run are taken out of the ready queue, and the others
+/// park themselves before returning to user space
+fn stop_process(process_inner: &mut ProcessControlBlockInner, current: &Arc<TaskControlBlock>) {
+    process_inner.stopped = true;
+    let ready_tasks: Vec<_> = process_inner
+        .tasks
+        .iter()
+        .flatten()
+        .filter(|task| {
+            !Arc::ptr_eq(task, current)
+                && task.inner_exclusive_access().task_status == TaskStatus::Ready
+        })
+        .cloned()
+        .collect();
+    for task in ready_tasks {
+        remove_task(Arc::clone(&task));
+        task.inner_exclusive_access().task_status = TaskStatus::Blocked;
+        process_inner.stopped_tasks.push(task);
+    }
 }
 
 /// the inactive(blocked) tasks are removed when the PCB is deallocated.(called by exit_current_and_run_next)
@@ -203,4 +540,5 @@ pub fn remove_inactive_task(task: Arc<TaskControlBlock>) {
     remove_task(Arc::clone(&task));
     trace!("kernel: remove_inactive_task .. remove_timer");
     remove_timer(Arc::clone(&task));
+    futex_remove_task(&task);
 }
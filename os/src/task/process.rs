@@ -3,12 +3,16 @@
 use super::id::RecycleAllocator;
 use super::manager::insert_into_pid2process;
 use super::TaskControlBlock;
-use super::{add_task, SignalFlags};
+use super::{SignalAction, SIG_IGN};
+use super::{add_task, ResourceLimits, SignalActions, SignalFlags, RLIMIT_AS, RLIMIT_NOFILE};
+use super::{IntervalTimer, Mailbox, ITIMER_COUNT};
 use super::{pid_alloc, PidHandle};
+use crate::config::{PAGE_SIZE, USER_STACK_SIZE};
 use crate::fs::{File, Stdin, Stdout};
 use crate::mm::{translated_refmut, MemorySet, KERNEL_SPACE};
-use crate::sync::{Condvar, Mutex, Semaphore, UPSafeCell};
+use crate::sync::{Barrier, Condvar, Mutex, RwLock, Semaphore, UPSafeCell};
 use crate::trap::{trap_handler, TrapContext};
+use alloc::collections::VecDeque;
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
@@ -33,12 +37,38 @@ pub struct ProcessControlBlockInner {
     pub parent: Option<Weak<ProcessControlBlock>>,
     /// children process
     pub children: Vec<Arc<ProcessControlBlock>>,
+    /// threads blocked in `sys_waitpid` until a child exits
+    pub wait_queue: VecDeque<Arc<TaskControlBlock>>,
     /// exit code
     pub exit_code: i32,
     /// file descriptor table
     pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// current working directory, a normalized absolute path
+    pub cwd: String,
     /// signal flags
     pub signals: SignalFlags,
+    /// signal actions
+    pub signal_actions: SignalActions,
+    /// is the process stopped by a stop signal?
+    pub stopped: bool,
+    /// threads parked until the process is continued
+    pub stopped_tasks: Vec<Arc<TaskControlBlock>>,
+    /// threads reading or polling a signalfd, woken up by a new signal
+    pub signalfd_waiters: Vec<Arc<TaskControlBlock>>,
+    /// resource limits
+    pub rlimits: ResourceLimits,
+    /// interval timers, indexed by ITIMER_REAL, ITIMER_VIRTUAL and ITIMER_PROF
+    pub itimers: [IntervalTimer; ITIMER_COUNT],
+    /// messages sent to this process
+    pub mailbox: Mailbox,
+    /// user time of exited threads, in microseconds
+    pub user_time_us: usize,
+    /// kernel time of exited threads, in microseconds
+    pub kernel_time_us: usize,
+    /// user time of reaped children, in microseconds
+    pub children_user_time_us: usize,
+    /// kernel time of reaped children, in microseconds
+    pub children_kernel_time_us: usize,
     /// tasks(also known as threads)
     pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
     /// task resource allocator
@@ -49,20 +79,30 @@ pub struct ProcessControlBlockInner {
     pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
     /// condvar list
     pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    /// rwlock list
+    pub rwlock_list: Vec<Option<Arc<RwLock>>>,
+    /// barrier list
+    pub barrier_list: Vec<Option<Arc<Barrier>>>,
     /// enable deadlock detect
     pub deadlock_detect: bool,
     /// mutex_available
     pub mutex_available: Vec<usize>,
     /// semaphore_available
     pub semaphore_available: Vec<usize>,
+    /// rwlock_available, in units of RWLOCK_MAX_READERS per rwlock
+    pub rwlock_available: Vec<usize>,
     /// mutex_allocation
     pub mutex_allocation: Vec<Vec<usize>>,
     /// semaphore_allocation
     pub semaphore_allocation: Vec<Vec<usize>>,
+    /// rwlock_allocation
+    pub rwlock_allocation: Vec<Vec<usize>>,
     /// mutex_need
     pub mutex_need: Vec<Vec<usize>>,
     /// semaphore_need
     pub semaphore_need: Vec<Vec<usize>>,
+    /// rwlock_need
+    pub rwlock_need: Vec<Vec<usize>>,
 }
 
 impl ProcessControlBlockInner {
@@ -71,13 +111,18 @@ impl ProcessControlBlockInner {
     pub fn get_user_token(&self) -> usize {
         self.memory_set.token()
     }
-    /// allocate a new file descriptor
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
+    /// allocate a new file descriptor, return None if RLIMIT_NOFILE is reached
+    pub fn alloc_fd(&mut self) -> Option<usize> {
+        let limit = self.rlimits.cur(RLIMIT_NOFILE);
+        if let Some(fd) =
+            (0..self.fd_table.len().min(limit)).find(|fd| self.fd_table[*fd].is_none())
+        {
+            Some(fd)
+        } else if self.fd_table.len() < limit {
             self.fd_table.push(None);
-            self.fd_table.len() - 1
+            Some(self.fd_table.len() - 1)
+        } else {
+            None
         }
     }
     /// allocate a new task id
@@ -88,6 +133,14 @@ impl ProcessControlBlockInner {
     pub fn dealloc_tid(&mut self, tid: usize) {
         self.task_res_allocator.dealloc(tid)
     }
+    /// the size of the user stack of a new thread
+    pub fn ustack_size(&self) -> usize {
+        ustack_size(&self.rlimits, &self.memory_set)
+    }
+    /// whether the user stack and trap context of a new thread fit in RLIMIT_AS
+    pub fn user_res_fits(&self) -> bool {
+        user_res_fits(&self.rlimits, &self.memory_set)
+    }
     /// the count of tasks(threads) in this process
     pub fn thread_count(&self) -> usize {
         self.tasks.len()
@@ -96,6 +149,85 @@ impl ProcessControlBlockInner {
     pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
         self.tasks[tid].as_ref().unwrap().clone()
     }
+    /// Banker's safety check over the mutex, semaphore and rwlock columns
+    /// together: can every thread alive get what it is waiting for and run
+    /// to the end?
+    ///
+    /// Must not be called with the inner of a thread borrowed.
+    pub fn is_safe_state(&self) -> bool {
+        let column = |row: &Vec<usize>, id: usize| row.get(id).copied().unwrap_or(0);
+        // (available, allocation, need) of each kind of resource
+        let resources = [
+            (&self.mutex_available, &self.mutex_allocation, &self.mutex_need),
+            (
+                &self.semaphore_available,
+                &self.semaphore_allocation,
+                &self.semaphore_need,
+            ),
+            (&self.rwlock_available, &self.rwlock_allocation, &self.rwlock_need),
+        ];
+        let mut work: Vec<Vec<usize>> = resources
+            .iter()
+            .map(|(available, _, _)| (*available).clone())
+            .collect();
+        // an exited thread is not waiting for anything, but it does not
+        // release what it holds either
+        let mut finish: Vec<bool> = self
+            .tasks
+            .iter()
+            .map(|task| {
+                task.as_ref()
+                    .map_or(true, |task| task.inner_exclusive_access().exit_code.is_some())
+            })
+            .collect();
+        loop {
+            let runnable = (0..self.tasks.len()).find(|&tid| {
+                !finish[tid]
+                    && resources.iter().zip(work.iter()).all(|((_, _, need), work)| {
+                        work.iter()
+                            .enumerate()
+                            .all(|(id, work)| column(&need[tid], id) <= *work)
+                    })
+            });
+            match runnable {
+                Some(tid) => {
+                    finish[tid] = true;
+                    for ((_, allocation, _), work) in resources.iter().zip(work.iter_mut()) {
+                        for (id, work) in work.iter_mut().enumerate() {
+                            *work += column(&allocation[tid], id);
+                        }
+                    }
+                }
+                None => return finish.iter().all(|finished| *finished),
+            }
+        }
+    }
+    /// user and kernel time of this process in microseconds, the threads still
+    /// alive included. Must not be called with the inner of a thread borrowed.
+    pub fn cpu_time_us(&self) -> (usize, usize) {
+        let mut user_time_us = self.user_time_us;
+        let mut kernel_time_us = self.kernel_time_us;
+        for task in self.tasks.iter().flatten() {
+            let task_inner = task.inner_exclusive_access();
+            user_time_us += task_inner.user_time_us;
+            kernel_time_us += task_inner.kernel_time_us;
+        }
+        (user_time_us, kernel_time_us)
+    }
+}
+
+/// the size of the user stack of a new thread in `memory_set`, the tls block
+/// is carved from the stack on top of RLIMIT_STACK
+fn ustack_size(rlimits: &ResourceLimits, memory_set: &MemorySet) -> usize {
+    let tls_size = memory_set.tls.map_or(0, |tls| tls.reserved_size());
+    (rlimits.ustack_size() + tls_size).min(USER_STACK_SIZE)
+}
+
+/// whether `memory_set` stays within RLIMIT_AS after mapping the user stack
+/// and trap context of a new thread
+fn user_res_fits(rlimits: &ResourceLimits, memory_set: &MemorySet) -> bool {
+    memory_set.mapped_size() + ustack_size(rlimits, memory_set) + PAGE_SIZE
+        <= rlimits.cur(RLIMIT_AS)
 }
 
 impl ProcessControlBlock {
@@ -104,10 +236,18 @@ impl ProcessControlBlock {
         self.inner.exclusive_access()
     }
     /// new process from elf file
-    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+    ///
+    /// Return None if the tls block of the image does not leave a page of
+    /// user stack, as in `exec`.
+    pub fn new(elf_data: &[u8]) -> Option<Arc<Self>> {
         trace!("kernel: ProcessControlBlock::new");
         // memory_set with elf program headers/trampoline/trap context/user stack
         let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        if let Some(tls) = memory_set.tls {
+            if tls.reserved_size() + PAGE_SIZE > USER_STACK_SIZE {
+                return None;
+            }
+        }
         // allocate a pid
         let pid_handle = pid_alloc();
         let process = Arc::new(Self {
@@ -118,28 +258,46 @@ impl ProcessControlBlock {
                     memory_set,
                     parent: None,
                     children: Vec::new(),
+                    wait_queue: VecDeque::new(),
                     exit_code: 0,
                     fd_table: vec![
                         // 0 -> stdin
-                        Some(Arc::new(Stdin)),
+                        Some(Arc::new(Stdin::new())),
                         // 1 -> stdout
                         Some(Arc::new(Stdout)),
                         // 2 -> stderr
                         Some(Arc::new(Stdout)),
                     ],
+                    cwd: String::from("/"),
                     signals: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    stopped: false,
+                    stopped_tasks: Vec::new(),
+                    signalfd_waiters: Vec::new(),
+                    rlimits: ResourceLimits::new(),
+                    itimers: [IntervalTimer::default(); ITIMER_COUNT],
+                    mailbox: Mailbox::default(),
+                    user_time_us: 0,
+                    kernel_time_us: 0,
+                    children_user_time_us: 0,
+                    children_kernel_time_us: 0,
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    rwlock_list: Vec::new(),
+                    barrier_list: Vec::new(),
                     deadlock_detect: false,
                     mutex_available: Vec::new(),
                     semaphore_available: Vec::new(),
+                    rwlock_available: Vec::new(),
                     mutex_allocation: Vec::new(),
                     semaphore_allocation: Vec::new(),
+                    rwlock_allocation: Vec::new(),
                     mutex_need: Vec::new(),
                     semaphore_need: Vec::new(),
+                    rwlock_need: Vec::new(),
                 })
             },
         });
@@ -153,16 +311,20 @@ impl ProcessControlBlock {
         // prepare trap_cx of main thread
         let task_inner = task.inner_exclusive_access();
         let trap_cx = task_inner.get_trap_cx();
-        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        let user_sp = task_inner.res.as_ref().unwrap().user_sp();
+        let tls_base = task_inner.res.as_ref().unwrap().tls_base();
         let kstack_top = task.kstack.get_top();
         drop(task_inner);
         *trap_cx = TrapContext::app_init_context(
             entry_point,
-            ustack_top,
+            user_sp,
             KERNEL_SPACE.exclusive_access().token(),
             kstack_top,
             trap_handler as usize,
         );
+        if let Some(tls_base) = tls_base {
+            trap_cx.set_tp(tls_base);
+        }
         // add main thread to the process
         let mut process_inner = process.inner_exclusive_access();
         process_inner.tasks.push(Some(Arc::clone(&task)));
@@ -172,36 +334,59 @@ impl ProcessControlBlock {
         process_inner.semaphore_allocation.push(Vec::new());
         process_inner.mutex_need.push(Vec::new());
         process_inner.semaphore_need.push(Vec::new());
+        process_inner.rwlock_allocation.push(Vec::new());
+        process_inner.rwlock_need.push(Vec::new());
 
         drop(process_inner);
         insert_into_pid2process(process.getpid(), Arc::clone(&process));
         // add main thread to scheduler
         add_task(task);
-        process
+        Some(process)
     }
 
     /// Only support processes with a single thread.
-    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) {
+    ///
+    /// Return false and keep the old image if the tls block of the new one
+    /// does not leave a page of user stack, or the new image with the stack
+    /// of the main thread exceeds RLIMIT_AS.
+    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) -> bool {
         trace!("kernel: exec");
         assert_eq!(self.inner_exclusive_access().thread_count(), 1);
         // memory_set with elf program headers/trampoline/trap context/user stack
         trace!("kernel: exec .. MemorySet::from_elf");
         let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        if let Some(tls) = memory_set.tls {
+            if tls.reserved_size() + PAGE_SIZE > USER_STACK_SIZE {
+                return false;
+            }
+        }
+        if !user_res_fits(&self.inner_exclusive_access().rlimits, &memory_set) {
+            return false;
+        }
         let new_token = memory_set.token();
         // substitute memory_set
         trace!("kernel: exec .. substitute memory_set");
         self.inner_exclusive_access().memory_set = memory_set;
+        // the handlers are gone with the old image, ignored signals stay ignored
+        for action in self.inner_exclusive_access().signal_actions.table.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
         // then we alloc user resource for main thread again
         // since memory_set has been changed
         trace!("kernel: exec .. alloc user resource for main thread again");
         let task = self.inner_exclusive_access().get_task(0);
+        let ustack_size = self.inner_exclusive_access().ustack_size();
         let mut task_inner = task.inner_exclusive_access();
         task_inner.res.as_mut().unwrap().ustack_base = ustack_base;
+        task_inner.res.as_mut().unwrap().ustack_size = ustack_size;
         task_inner.res.as_mut().unwrap().alloc_user_res();
         task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
+        task_inner.sigframe = 0;
         // push arguments on user stack
         trace!("kernel: exec .. push arguments on user stack");
-        let mut user_sp = task_inner.res.as_mut().unwrap().ustack_top();
+        let mut user_sp = task_inner.res.as_mut().unwrap().user_sp();
         user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
         let argv_base = user_sp;
         let mut argv: Vec<_> = (0..=args.len())
@@ -236,7 +421,11 @@ impl ProcessControlBlock {
         );
         trap_cx.x[10] = args.len();
         trap_cx.x[11] = argv_base;
+        if let Some(tls_base) = task_inner.res.as_ref().unwrap().tls_base() {
+            trap_cx.set_tp(tls_base);
+        }
         *task_inner.get_trap_cx() = trap_cx;
+        true
     }
 
     /// Only support processes with a single thread.
@@ -266,21 +455,41 @@ impl ProcessControlBlock {
                     memory_set,
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
+                    wait_queue: VecDeque::new(),
                     exit_code: 0,
                     fd_table: new_fd_table,
+                    cwd: parent.cwd.clone(),
                     signals: SignalFlags::empty(),
+                    signal_actions: parent.signal_actions.clone(),
+                    stopped: false,
+                    stopped_tasks: Vec::new(),
+                    signalfd_waiters: Vec::new(),
+                    rlimits: parent.rlimits,
+                    // interval timers are not inherited
+                    itimers: [IntervalTimer::default(); ITIMER_COUNT],
+                    mailbox: Mailbox::default(),
+                    user_time_us: 0,
+                    kernel_time_us: 0,
+                    children_user_time_us: 0,
+                    children_kernel_time_us: 0,
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    rwlock_list: Vec::new(),
+                    barrier_list: Vec::new(),
                     deadlock_detect: parent.deadlock_detect,
-                    mutex_available: parent.mutex_available.clone(),
-                    semaphore_available: parent.semaphore_available.clone(),
-                    mutex_allocation: parent.mutex_allocation.clone(),
-                    semaphore_allocation: parent.semaphore_allocation.clone(),
-                    mutex_need: parent.mutex_need.clone(),
-                    semaphore_need: parent.semaphore_need.clone()
+                    // the child has no sync objects, only a row for its main thread
+                    mutex_available: Vec::new(),
+                    semaphore_available: Vec::new(),
+                    rwlock_available: Vec::new(),
+                    mutex_allocation: vec![Vec::new()],
+                    semaphore_allocation: vec![Vec::new()],
+                    rwlock_allocation: vec![Vec::new()],
+                    mutex_need: vec![Vec::new()],
+                    semaphore_need: vec![Vec::new()],
+                    rwlock_need: vec![Vec::new()],
                 })
             },
         });
@@ -300,6 +509,17 @@ impl ProcessControlBlock {
             // but mention that we allocate a new kstack here
             false,
         ));
+        // the user stack of the child is copied from the parent, so it keeps its
+        // size and the signal frames on it, as well as the signal mask
+        let parent_task = parent.get_task(0);
+        let parent_task_inner = parent_task.inner_exclusive_access();
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.res.as_mut().unwrap().ustack_size =
+            parent_task_inner.res.as_ref().unwrap().ustack_size;
+        task_inner.signal_mask = parent_task_inner.signal_mask;
+        task_inner.sigframe = parent_task_inner.sigframe;
+        drop(task_inner);
+        drop(parent_task_inner);
         // attach task to child process
         let mut child_inner = child.inner_exclusive_access();
         child_inner.tasks.push(Some(Arc::clone(&task)));
@@ -8,6 +8,7 @@ use super::__switch;
 use super::{fetch_task, TaskStatus};
 use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
 use crate::sync::UPSafeCell;
+use crate::timer::{check_timer, get_time_us};
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
 use lazy_static::*;
@@ -59,6 +60,8 @@ pub fn run_tasks() {
             let mut task_inner = task.inner_exclusive_access();
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
             task_inner.task_status = TaskStatus::Running;
+            // the task starts to consume cpu time from now on
+            task_inner.last_time_us = get_time_us();
             // release coming task_inner manually
             drop(task_inner);
             // release coming task TCB manually
@@ -70,6 +73,10 @@ pub fn run_tasks() {
             }
         } else {
             warn!("no tasks available in run_tasks");
+            drop(processor);
+            // all tasks are blocked, only an expired timer wakes one up,
+            // either directly or by a signal interrupting its wait
+            check_timer();
         }
     }
 }
@@ -114,6 +121,24 @@ pub fn current_trap_cx_user_va() -> usize {
         .trap_cx_user_va()
 }
 
+/// Charge the time of current task since its last timestamp to user time,
+/// called when trapping into the kernel
+pub fn current_charge_user_time() {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .charge_user_time();
+}
+
+/// Charge the time of current task since its last timestamp to kernel time,
+/// called when returning to user space
+pub fn current_charge_kernel_time() {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .charge_kernel_time();
+}
+
 /// get the top addr of kernel stack
 pub fn current_kstack_top() -> usize {
     current_task().unwrap().kstack.get_top()
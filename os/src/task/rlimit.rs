@@ -0,0 +1,99 @@
+//! Resource limits(rlimit) of a process
+//!
+//! The limits are inherited by the child process on fork, and checked by the
+//! kernel where the corresponding resource is allocated: file descriptors in
+//! `alloc_fd`, threads and their stacks in `sys_thread_create`, the image and
+//! the main stack in `exec`, mapped memory in `sys_mmap`, and CPU time on the
+//! timer tick.
+
+use crate::config::{PAGE_SIZE, USER_STACK_SIZE};
+
+/// No limit on a resource
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// CPU time of the process in seconds
+pub const RLIMIT_CPU: usize = 0;
+/// Size of the user stack of a thread in bytes, read at thread creation and exec
+pub const RLIMIT_STACK: usize = 3;
+/// Number of threads in the process
+///
+/// Unlike POSIX, where it counts the processes of a user, this kernel has no
+/// users, so the limit caps the threads of a single process instead.
+pub const RLIMIT_NPROC: usize = 6;
+/// Number of open file descriptors, i.e. the length of `fd_table`
+pub const RLIMIT_NOFILE: usize = 7;
+/// Size of the address space in bytes, the trampoline excluded
+pub const RLIMIT_AS: usize = 9;
+/// The number of resources
+pub const RLIM_NLIMITS: usize = 16;
+
+/// A soft and a hard limit of a resource
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct RLimit {
+    /// soft limit, which is enforced by the kernel
+    pub rlim_cur: usize,
+    /// hard limit, the ceiling of the soft limit
+    pub rlim_max: usize,
+}
+
+impl RLimit {
+    /// Create a limit with the same soft and hard value
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            rlim_cur: limit,
+            rlim_max: limit,
+        }
+    }
+}
+
+/// All resource limits of a process
+#[derive(Copy, Clone)]
+pub struct ResourceLimits {
+    limits: [RLimit; RLIM_NLIMITS],
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourceLimits {
+    /// Create the default limits, only the user stack is limited by default
+    pub fn new() -> Self {
+        let mut limits = [RLimit::new(RLIM_INFINITY); RLIM_NLIMITS];
+        limits[RLIMIT_STACK] = RLimit::new(USER_STACK_SIZE);
+        Self { limits }
+    }
+    /// Get the limit of a resource
+    pub fn get(&self, resource: usize) -> Option<RLimit> {
+        self.limits.get(resource).copied()
+    }
+    /// Set the limit of a resource
+    ///
+    /// Return false if the resource does not exist, the soft limit exceeds
+    /// the hard limit or the hard limit is raised, since there is no
+    /// privileged process to do that.
+    pub fn set(&mut self, resource: usize, limit: RLimit) -> bool {
+        let old = match self.limits.get(resource) {
+            Some(old) => *old,
+            None => return false,
+        };
+        if limit.rlim_cur > limit.rlim_max || limit.rlim_max > old.rlim_max {
+            return false;
+        }
+        self.limits[resource] = limit;
+        true
+    }
+    /// The soft limit of a resource
+    pub fn cur(&self, resource: usize) -> usize {
+        self.limits[resource].rlim_cur
+    }
+    /// The size of the user stack of a new thread, at least one page and at
+    /// most the space reserved for a stack
+    pub fn ustack_size(&self) -> usize {
+        let size = self.cur(RLIMIT_STACK).min(USER_STACK_SIZE);
+        ((size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE).max(PAGE_SIZE)
+    }
+}
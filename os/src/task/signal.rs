@@ -5,35 +5,157 @@ use bitflags::*;
 bitflags! {
     /// Signal flags
     pub struct SignalFlags: u32 {
+        /// Hangup
+        const SIGHUP    = 1 << 1;
         /// Interrupt
         const SIGINT    = 1 << 2;
+        /// Quit
+        const SIGQUIT   = 1 << 3;
         /// Illegal instruction
         const SIGILL    = 1 << 4;
+        /// Trace or breakpoint trap
+        const SIGTRAP   = 1 << 5;
         /// Abort
         const SIGABRT   = 1 << 6;
+        /// Bus error
+        const SIGBUS    = 1 << 7;
         /// Floating point exception
         const SIGFPE    = 1 << 8;
+        /// Kill, can not be caught, blocked or ignored
+        const SIGKILL   = 1 << 9;
+        /// User-defined signal 1
+        const SIGUSR1   = 1 << 10;
         /// Segmentation fault
         const SIGSEGV   = 1 << 11;
+        /// User-defined signal 2
+        const SIGUSR2   = 1 << 12;
+        /// Write to a pipe with no readers
+        const SIGPIPE   = 1 << 13;
+        /// Timer signal from alarm
+        const SIGALRM   = 1 << 14;
+        /// Termination
+        const SIGTERM   = 1 << 15;
+        /// Stack fault on coprocessor
+        const SIGSTKFLT = 1 << 16;
+        /// Child stopped or terminated
+        const SIGCHLD   = 1 << 17;
+        /// Continue if stopped
+        const SIGCONT   = 1 << 18;
+        /// Stop, can not be caught, blocked or ignored
+        const SIGSTOP   = 1 << 19;
+        /// Stop typed at terminal
+        const SIGTSTP   = 1 << 20;
+        /// Terminal input for background process
+        const SIGTTIN   = 1 << 21;
+        /// Terminal output for background process
+        const SIGTTOU   = 1 << 22;
+        /// Urgent condition on socket
+        const SIGURG    = 1 << 23;
+        /// CPU time limit exceeded
+        const SIGXCPU   = 1 << 24;
+        /// File size limit exceeded
+        const SIGXFSZ   = 1 << 25;
+        /// Virtual alarm clock
+        const SIGVTALRM = 1 << 26;
+        /// Profiling timer expired
+        const SIGPROF   = 1 << 27;
+        /// Window resize signal
+        const SIGWINCH  = 1 << 28;
+        /// I/O now possible
+        const SIGIO     = 1 << 29;
+        /// Power failure
+        const SIGPWR    = 1 << 30;
+        /// Bad system call
+        const SIGSYS    = 1 << 31;
     }
 }
 
+/// Default action of a signal without a handler
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SignalDefault {
+    /// terminate the process
+    Terminate,
+    /// terminate the process, and dump core on a real system
+    CoreDump,
+    /// stop the process
+    Stop,
+    /// continue the process if it is stopped
+    Continue,
+    /// ignore the signal
+    Ignore,
+}
+
 impl SignalFlags {
-    /// convert signal flag to integer & string
-    pub fn check_error(&self) -> Option<(i32, &'static str)> {
-        if self.contains(Self::SIGINT) {
-            Some((-2, "Killed, SIGINT=2"))
-        } else if self.contains(Self::SIGILL) {
-            Some((-4, "Illegal Instruction, SIGILL=4"))
-        } else if self.contains(Self::SIGABRT) {
-            Some((-6, "Aborted, SIGABRT=6"))
-        } else if self.contains(Self::SIGFPE) {
-            Some((-8, "Erroneous Arithmetic Operation, SIGFPE=8"))
-        } else if self.contains(Self::SIGSEGV) {
-            Some((-11, "Segmentation Fault, SIGSEGV=11"))
+    /// Signals that can not be caught, blocked or ignored
+    pub const UNCATCHABLE: Self = Self::from_bits_truncate(Self::SIGKILL.bits | Self::SIGSTOP.bits);
+    /// Signals whose default action stops the process
+    pub const STOP_SIGNALS: Self = Self::from_bits_truncate(
+        Self::SIGSTOP.bits | Self::SIGTSTP.bits | Self::SIGTTIN.bits | Self::SIGTTOU.bits,
+    );
+
+    /// the default action of a single signal
+    pub fn default_action(&self) -> SignalDefault {
+        if Self::STOP_SIGNALS.contains(*self) {
+            SignalDefault::Stop
+        } else if *self == Self::SIGCONT {
+            SignalDefault::Continue
+        } else if (Self::SIGCHLD | Self::SIGURG | Self::SIGWINCH).contains(*self) {
+            SignalDefault::Ignore
+        } else if (Self::SIGQUIT
+            | Self::SIGILL
+            | Self::SIGTRAP
+            | Self::SIGABRT
+            | Self::SIGBUS
+            | Self::SIGFPE
+            | Self::SIGSEGV
+            | Self::SIGXCPU
+            | Self::SIGXFSZ
+            | Self::SIGSYS)
+            .contains(*self)
+        {
+            SignalDefault::CoreDump
         } else {
-            // warn!("[kernel] signalflags check_error  {:?}", self);
-            None
+            SignalDefault::Terminate
         }
     }
+
+    /// convert signal flag to integer & string
+    ///
+    /// Return the first signal whose default action terminates the process.
+    pub fn check_error(&self) -> Option<(i32, &'static str)> {
+        let signum = (1..32).find(|signum| {
+            let flag = Self::from_bits_truncate(1 << signum);
+            self.contains(flag)
+                && matches!(
+                    flag.default_action(),
+                    SignalDefault::Terminate | SignalDefault::CoreDump
+                )
+        })?;
+        let msg = match signum {
+            1 => "Hangup, SIGHUP=1",
+            2 => "Killed, SIGINT=2",
+            3 => "Quit, SIGQUIT=3",
+            4 => "Illegal Instruction, SIGILL=4",
+            5 => "Trace Trap, SIGTRAP=5",
+            6 => "Aborted, SIGABRT=6",
+            7 => "Bus Error, SIGBUS=7",
+            8 => "Erroneous Arithmetic Operation, SIGFPE=8",
+            9 => "Killed, SIGKILL=9",
+            10 => "User Defined Signal 1, SIGUSR1=10",
+            11 => "Segmentation Fault, SIGSEGV=11",
+            12 => "User Defined Signal 2, SIGUSR2=12",
+            13 => "Broken Pipe, SIGPIPE=13",
+            14 => "Alarm Clock, SIGALRM=14",
+            15 => "Terminated, SIGTERM=15",
+            16 => "Stack Fault, SIGSTKFLT=16",
+            24 => "CPU Time Limit Exceeded, SIGXCPU=24",
+            25 => "File Size Limit Exceeded, SIGXFSZ=25",
+            26 => "Virtual Timer Expired, SIGVTALRM=26",
+            27 => "Profiling Timer Expired, SIGPROF=27",
+            29 => "I/O Possible, SIGIO=29",
+            30 => "Power Failure, SIGPWR=30",
+            _ => "Bad System Call, SIGSYS=31",
+        };
+        Some((-signum, msg))
+    }
 }
@@ -1,10 +1,15 @@
 //! Types related to task management & Functions for completely changing TCB
 
 use super::id::TaskUserRes;
-use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext};
+use super::{kstack_alloc, KernelStack, ProcessControlBlock, SignalFlags, TaskContext};
+use crate::config::DEFAULT_PRIORITY;
+use crate::sync::MutexBlockingInner;
+use crate::timer::get_time_us;
 use crate::trap::TrapContext;
 use crate::{mm::PhysPageNum, sync::UPSafeCell};
+use alloc::collections::VecDeque;
 use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use core::cell::RefMut;
 
 /// Task control block structure
@@ -41,6 +46,34 @@ pub struct TaskControlBlockInner {
     pub task_status: TaskStatus,
     /// It is set when active exit or execution error occurs
     pub exit_code: Option<i32>,
+    /// A detached thread is reclaimed as soon as it exits and can not be joined
+    pub detached: bool,
+    /// Threads blocked in `sys_waittid` until this thread exits
+    pub join_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// Time spent in user mode, in microseconds
+    pub user_time_us: usize,
+    /// Time spent in kernel mode, in microseconds
+    pub kernel_time_us: usize,
+    /// When the task last entered/left the kernel or was switched in, in microseconds
+    pub last_time_us: usize,
+    /// Signals blocked by this thread
+    pub signal_mask: SignalFlags,
+    /// The mask to restore once the signal that interrupts a wait under a
+    /// temporary mask, as in `sys_ppoll`, is handled
+    pub saved_signal_mask: Option<SignalFlags>,
+    /// Address of the newest signal frame on the user stack, 0 if no handler is running
+    pub sigframe: usize,
+    /// A signal may wake the task up from the wait it is blocked in
+    pub interruptible: bool,
+    /// Priority set by `sys_set_priority`
+    pub priority: usize,
+    /// Priority the task is scheduled with, raised above `priority` while a
+    /// task of higher priority waits on a blocking mutex held by this one
+    pub effective_priority: usize,
+    /// The blocking mutex this task waits on
+    pub blocked_on: Option<Weak<UPSafeCell<MutexBlockingInner>>>,
+    /// The blocking mutexes this task holds
+    pub held_mutexes: Vec<Weak<UPSafeCell<MutexBlockingInner>>>,
 }
 
 impl TaskControlBlockInner {
@@ -52,6 +85,20 @@ impl TaskControlBlockInner {
     fn get_status(&self) -> TaskStatus {
         self.task_status
     }
+
+    /// Charge the time since the last timestamp to user time
+    pub fn charge_user_time(&mut self) {
+        let now = get_time_us();
+        self.user_time_us += now - self.last_time_us;
+        self.last_time_us = now;
+    }
+
+    /// Charge the time since the last timestamp to kernel time
+    pub fn charge_kernel_time(&mut self) {
+        let now = get_time_us();
+        self.kernel_time_us += now - self.last_time_us;
+        self.last_time_us = now;
+    }
 }
 
 impl TaskControlBlock {
@@ -75,6 +122,19 @@ impl TaskControlBlock {
                     task_cx: TaskContext::goto_trap_return(kstack_top),
                     task_status: TaskStatus::Ready,
                     exit_code: None,
+                    detached: false,
+                    join_queue: VecDeque::new(),
+                    user_time_us: 0,
+                    kernel_time_us: 0,
+                    last_time_us: 0,
+                    signal_mask: SignalFlags::empty(),
+                    saved_signal_mask: None,
+                    sigframe: 0,
+                    interruptible: false,
+                    priority: DEFAULT_PRIORITY,
+                    effective_priority: DEFAULT_PRIORITY,
+                    blocked_on: None,
+                    held_mutexes: Vec::new(),
                 })
             },
         }
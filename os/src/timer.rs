@@ -5,9 +5,12 @@ use core::cmp::Ordering;
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
 use crate::sync::UPSafeCell;
-use crate::task::{current_task, wakeup_task, TaskControlBlock};
+use crate::task::{
+    current_task, expire_real_timer, wakeup_task, ProcessControlBlock, TaskControlBlock,
+};
 use alloc::collections::BinaryHeap;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use lazy_static::*;
 use riscv::register::time;
 /// The number of ticks per second
@@ -38,12 +41,20 @@ pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
 
+/// What to do when a timer expires
+pub enum TimerAction {
+    /// Wake up a blocked task
+    Wakeup(Arc<TaskControlBlock>),
+    /// Expire the real interval timer of a process
+    RealTimer(Weak<ProcessControlBlock>),
+}
+
 /// condvar for timer
 pub struct TimerCondVar {
     /// The time when the timer expires, in milliseconds
     pub expire_ms: usize,
-    /// The task to be woken up when the timer expires
-    pub task: Arc<TaskControlBlock>,
+    /// The action taken when the timer expires
+    pub action: TimerAction,
 }
 
 impl PartialEq for TimerCondVar {
@@ -79,39 +90,71 @@ pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
         current_task().unwrap().process.upgrade().unwrap().getpid()
     );
     let mut timers = TIMERS.exclusive_access();
-    timers.push(TimerCondVar { expire_ms, task });
+    timers.push(TimerCondVar {
+        expire_ms,
+        action: TimerAction::Wakeup(task),
+    });
+}
+
+/// Add a timer for the real interval timer of a process
+pub fn add_real_timer(expire_ms: usize, process: Weak<ProcessControlBlock>) {
+    trace!("kernel: add_real_timer");
+    let mut timers = TIMERS.exclusive_access();
+    timers.push(TimerCondVar {
+        expire_ms,
+        action: TimerAction::RealTimer(process),
+    });
 }
 
 /// Remove a timer
 pub fn remove_timer(task: Arc<TaskControlBlock>) {
     //trace!("kernel:pid[{}] remove_timer", current_task().unwrap().process.upgrade().unwrap().getpid());
     trace!("kernel: remove_timer");
-    let mut timers = TIMERS.exclusive_access();
-    let mut temp = BinaryHeap::<TimerCondVar>::new();
-    for condvar in timers.drain() {
-        if Arc::as_ptr(&task) != Arc::as_ptr(&condvar.task) {
-            temp.push(condvar);
-        }
-    }
-    timers.clear();
-    timers.append(&mut temp);
+    TIMERS
+        .exclusive_access()
+        .retain(|condvar| match &condvar.action {
+            TimerAction::Wakeup(t) => !Arc::ptr_eq(t, &task),
+            _ => true,
+        });
     trace!("kernel: remove_timer END");
 }
 
+/// Remove the timer for the real interval timer of a process
+pub fn remove_real_timer(process: &Arc<ProcessControlBlock>) {
+    trace!("kernel: remove_real_timer");
+    TIMERS
+        .exclusive_access()
+        .retain(|condvar| match &condvar.action {
+            TimerAction::RealTimer(p) => p.as_ptr() != Arc::as_ptr(process),
+            _ => true,
+        });
+}
+
 /// Check if the timer has expired
+///
+/// It is called by the idle loop as well, where there is no current task.
 pub fn check_timer() {
-    trace!(
-        "kernel:pid[{}] check_timer",
-        current_task().unwrap().process.upgrade().unwrap().getpid()
-    );
+    trace!("kernel: check_timer");
     let current_ms = get_time_ms();
     let mut timers = TIMERS.exclusive_access();
+    let mut expired = Vec::new();
     while let Some(timer) = timers.peek() {
         if timer.expire_ms <= current_ms {
-            wakeup_task(Arc::clone(&timer.task));
-            timers.pop();
+            expired.push(timers.pop().unwrap());
         } else {
             break;
         }
     }
+    // a periodic timer is added again when it expires, so release TIMERS first
+    drop(timers);
+    for timer in expired {
+        match timer.action {
+            TimerAction::Wakeup(task) => wakeup_task(task),
+            TimerAction::RealTimer(process) => {
+                if let Some(process) = process.upgrade() {
+                    expire_real_timer(&process);
+                }
+            }
+        }
+    }
 }
@@ -24,6 +24,10 @@ impl TrapContext {
     pub fn set_sp(&mut self, sp: usize) {
         self.x[2] = sp;
     }
+    /// put the tp(thread pointer) into x\[4\] field of TrapContext
+    pub fn set_tp(&mut self, tp: usize) {
+        self.x[4] = tp;
+    }
     /// init the trap context of an application
     pub fn app_init_context(
         entry: usize,
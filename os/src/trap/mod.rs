@@ -17,8 +17,10 @@ mod context;
 use crate::config::TRAMPOLINE;
 use crate::syscall::syscall;
 use crate::task::{
-    check_signals_of_current, current_add_signal, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, exit_current_and_run_next, suspend_current_and_run_next, SignalFlags,
+    check_cpu_timers_of_current, current_add_signal, current_charge_kernel_time,
+    current_charge_user_time, current_trap_cx, current_trap_cx_user_va, current_user_token,
+    exit_current_and_run_next, handle_signals_of_current, suspend_current_and_run_next,
+    SignalFlags,
 };
 use crate::timer::{check_timer, set_next_trigger};
 use core::arch::{asm, global_asm};
@@ -61,6 +63,7 @@ pub fn enable_timer_interrupt() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    current_charge_user_time();
     let scause = scause::read();
     let stval = stval::read();
     // trace!("into {:?}", scause.cause());
@@ -70,7 +73,10 @@ pub fn trap_handler() -> ! {
             let mut cx = current_trap_cx();
             cx.sepc += 4;
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
@@ -95,6 +101,7 @@ pub fn trap_handler() -> ! {
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
             check_timer();
+            check_cpu_timers_of_current();
             suspend_current_and_run_next();
         }
         _ => {
@@ -105,19 +112,20 @@ pub fn trap_handler() -> ! {
             );
         }
     }
-    // check signals
-    if let Some((errno, msg)) = check_signals_of_current() {
-        trace!("[kernel] trap_handler: .. check signals {}", msg);
-        exit_current_and_run_next(errno);
-    }
     trap_return();
 }
 
 /// return to user space
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // deliver signals to the user handlers, or kill the process
+    if let Some((errno, msg)) = handle_signals_of_current() {
+        trace!("[kernel] trap_return: .. handle signals {}", msg);
+        exit_current_and_run_next(errno);
+    }
     //disable_supervisor_interrupt();
     set_user_trap_entry();
+    current_charge_kernel_time();
     let trap_cx_user_va = current_trap_cx_user_va();
     let user_satp = current_user_token();
     extern "C" {
@@ -0,0 +1,113 @@
+//! Fork, exit and wait over and over, the free frames have to come back to
+//! where they were.
+//!
+//! It is built with the other ch8 apps by `make run` in `os`, run it from
+//! the user shell with `ch8_reap_frames`.
+
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+use user_lib::{exit, fork, sleep, thread_create, waitpid, yield_};
+
+const SYSCALL_SYSINFO: usize = 179;
+const ROUNDS: usize = 64;
+/// How long the orphans may take to be reaped by initproc, in milliseconds
+const REAP_TIMEOUT_MS: usize = 1000;
+
+/// linux `struct sysinfo`
+#[repr(C)]
+#[derive(Default)]
+struct SysInfo {
+    uptime: isize,
+    loads: [usize; 3],
+    totalram: usize,
+    freeram: usize,
+    pad: [usize; 4],
+    procs: u16,
+    totalhigh: usize,
+    freehigh: usize,
+    mem_unit: u32,
+}
+
+fn free_frames() -> usize {
+    let mut info = SysInfo::default();
+    let ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") &mut info as *mut SysInfo as usize => ret,
+            in("x17") SYSCALL_SYSINFO
+        );
+    }
+    assert_eq!(ret, 0);
+    info.freeram
+}
+
+fn worker(_arg: usize) -> ! {
+    // keep running while the main thread exits the process
+    loop {
+        yield_();
+    }
+}
+
+/// One round of the workload: a child that exits, a child whose extra
+/// thread is still running when it exits, and an orphan left to initproc
+fn round(i: usize) {
+    let pid = fork();
+    if pid == 0 {
+        exit(i as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, i as i32);
+
+    let pid = fork();
+    if pid == 0 {
+        let tid = thread_create(worker as usize, 0);
+        assert!(tid > 0);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+
+    let pid = fork();
+    if pid == 0 {
+        if fork() == 0 {
+            // the orphan outlives its parent a little
+            sleep(1);
+            exit(0);
+        }
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the first round and its orphan settle what is allocated only once
+    round(0);
+    sleep(100);
+    let baseline = free_frames();
+    for i in 1..=ROUNDS {
+        round(i);
+    }
+    // wait for initproc to reap the orphans
+    let mut waited_ms = 0;
+    while free_frames() != baseline && waited_ms < REAP_TIMEOUT_MS {
+        sleep(10);
+        waited_ms += 10;
+    }
+    let free = free_frames();
+    if free != baseline {
+        println!(
+            "ch8_reap_frames failed: {} free frames, {} before",
+            free, baseline
+        );
+        return -1;
+    }
+    println!("ch8_reap_frames passed!");
+    0
+}